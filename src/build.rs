@@ -1,34 +1,42 @@
 use crate::config::Config;
-use crate::context::{GeneratorContext, PostContext};
+use crate::context::{GeneratorContext, PostContext, PostLink, SiteConfig};
+use crate::diagram::Diagrams;
 use crate::dir;
+use crate::feed;
+use crate::functions;
+use crate::hash::content_hash;
+use crate::navigation;
+use crate::related;
+use crate::search;
+use crate::sitemap;
+use crate::text::summarize;
+use crate::theme;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use tera::{Context, Tera};
 
-use crate::markdown::MarkdownFile;
-use chrono::Datelike;
+use crate::markdown::{Asset, MarkdownFile, Outgoing, PostType};
+use chrono::{Datelike, NaiveDate, Utc};
 use log::{debug, error, info, warn};
 
-pub fn build(base_dir: PathBuf, config: Config) {
-    let template_path = base_dir
-        .join(".campfire")
-        .join(config.paths.templates.clone())
-        .canonicalize()
-        .unwrap()
-        .join("**")
-        .join("*.html");
-    let template_path = template_path.to_str().unwrap();
-    debug!("Using templates from {}", template_path);
-    let tera = match Tera::new(template_path) {
+pub fn build(base_dir: PathBuf, mut config: Config) {
+    if let Err(e) = theme::apply_theme_config(&base_dir, &mut config) {
+        error!("{}", e);
+        ::std::process::exit(1);
+    }
+    let tera = match theme::load_templates(&base_dir, &config) {
         Ok(t) => t,
         Err(e) => {
-            error!("Tera: Parsing error(s): {}", e);
+            error!("Tera: Parsing error(s): {:?}", e);
             ::std::process::exit(1);
         }
     };
 
     let files = dir::find_all_markdown_files(&base_dir, &config);
+    let notes = dir::find_all_note_paths(&base_dir);
 
     // Clean up output directory
     let campfire_dir = base_dir.join(".campfire");
@@ -39,32 +47,71 @@ pub fn build(base_dir: PathBuf, config: Config) {
     fs::create_dir(&output_dir).unwrap();
     fs::create_dir(output_dir.join("static")).unwrap();
 
+    let cache_dir = campfire_dir.join(&config.paths.cache);
+    let diagrams = Diagrams::new(&config.diagrams, cache_dir.clone());
+
     // Build global context
     let mut ctx = GeneratorContext {
         config,
         tera,
         base_dir,
         output_dir,
+        cache_dir,
+        diagrams,
+        notes,
         posts: vec![],
+        global_context: Context::new(),
+        flat_assets: Mutex::new(HashMap::new()),
     };
 
     // create posts and metadata for each entry
     for file in files {
         let post_context = create_post_metadata(&ctx, &file);
-        ctx.posts.push((file.into(), post_context));
+        ctx.posts.push((file, post_context));
     }
     ctx.posts.sort_by(|(_, a), (_, b)| b.date.cmp(&a.date));
+    if let Err(e) = navigation::check_generated_folders(&ctx) {
+        error!("{}", e);
+        ::std::process::exit(1);
+    }
 
-    // render individual posts
+    // render markdown of individual posts
     let mut posts: Vec<(MarkdownFile, PostContext)> = Vec::new();
+    let mut linked_posts = Vec::new();
+    let mut copied_assets = HashMap::new();
     for (file, post_context) in &ctx.posts {
-        let post_context = generate_post_and_copy_assets(&ctx, post_context, &file);
+        let (post_context, outgoing) =
+            render_post_and_copy_assets(&ctx, post_context, file, &mut copied_assets);
         posts.push((file.clone(), post_context));
+        linked_posts.push(outgoing.posts);
     }
     ctx.posts = posts;
+    add_related_posts(&mut ctx, &linked_posts);
+    navigation::add_previous_and_next(&mut ctx);
+    navigation::add_series(&mut ctx);
+    ctx.global_context = global_context(&ctx);
+    let ctx = functions::register_functions(ctx);
+
+    // render individual posts
+    for (file, post_context) in &ctx.posts {
+        generate_post(&ctx, file, post_context);
+    }
 
-    // render index & feed
-    generate_index_and_feed(&ctx);
+    // render index, series & feeds
+    generate_index(&ctx);
+    generate_not_found(&ctx);
+    navigation::generate_series_pages(&ctx);
+    navigation::generate_tag_pages(&ctx);
+    feed::generate_feeds(&ctx);
+    if ctx.config.search {
+        search::generate_search_index(&ctx);
+    }
+    if ctx.config.sitemap {
+        sitemap::generate_sitemap(&ctx);
+    }
+    if ctx.config.robots {
+        sitemap::generate_robots(&ctx);
+    }
 
     copy_static_files(&ctx);
 
@@ -76,28 +123,34 @@ fn create_post_metadata(ctx: &GeneratorContext, file: &MarkdownFile) -> PostCont
         .frontmatter
         .tags
         .iter()
-        .map(|t| t.clone())
-        .filter(|t| t.as_str() != &ctx.config.require_tag)
+        .filter(|&t| t.as_str() != ctx.config.require_tag)
+        .cloned()
         .collect();
     let author = match &file.frontmatter.author {
         Some(author) => author,
         None => &ctx.config.author,
     }
     .clone();
-    return PostContext {
+    // only pages may have no date, posts without one aren't published
+    let date = file.frontmatter.date;
+    let format = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+    PostContext {
         title: file.title(),
+        post_type: file.frontmatter.post_type,
         tags,
         author,
-        date: file
-            .frontmatter
-            .date
-            .unwrap()
-            .format("%Y-%m-%d")
-            .to_string(),
-        year: file.frontmatter.date.unwrap().year(),
-        month: file.frontmatter.date.unwrap().month(),
-        day: file.frontmatter.date.unwrap().day(),
+        date: date.map(format),
+        year: date.map(|date| date.year()),
+        month: date.map(|date| date.month()),
+        day: date.map(|date| date.day()),
+        updated: file.frontmatter.updated.or(date).map(format),
         markdown: "".into(),
+        summary: "".into(),
+        statistics: Default::default(),
+        related: vec![],
+        previous: None,
+        next: None,
+        series: None,
         original_file_name: file
             .path
             .strip_prefix(&ctx.base_dir)
@@ -106,78 +159,298 @@ fn create_post_metadata(ctx: &GeneratorContext, file: &MarkdownFile) -> PostCont
             .unwrap()
             .into(),
         relative_url: format!("{}/", file.slug(&ctx.base_dir)),
-    };
+    }
 }
 
-fn generate_post_and_copy_assets(
+fn render_post_and_copy_assets(
     ctx: &GeneratorContext,
     post_context: &PostContext,
     file: &MarkdownFile,
-) -> PostContext {
-    let file_dir = ctx.output_dir.join(file.slug(&ctx.base_dir));
-    let output_file = file_dir.join("index.html");
-
-    let (html, assets) = &file.render_to_html(&ctx);
+    copied_assets: &mut HashMap<PathBuf, (String, PathBuf)>,
+) -> (PostContext, Outgoing) {
+    let (html, summary, outgoing, statistics) = file.render_to_html(ctx);
     let post_context = PostContext {
-        markdown: html.into(),
+        summary: summary.unwrap_or_else(|| {
+            summarize(
+                &html,
+                file.frontmatter.description.as_deref(),
+                ctx.config.summary_words,
+            )
+        }),
+        markdown: html,
+        statistics,
         ..post_context.clone()
     };
 
-    let mut context = Context::new();
-    context.insert("post", &post_context);
-    context.insert("base_url", &ctx.config.base_url);
-    context.insert("site_title", &ctx.config.title());
+    for asset in &outgoing.assets {
+        copy_asset(ctx, asset, copied_assets);
+    }
+
+    (post_context, outgoing)
+}
+
+/// Ranks related posts for each post, using the posts they link to.
+fn add_related_posts(ctx: &mut GeneratorContext, linked_posts: &[Vec<PathBuf>]) {
+    // pages are neither related to posts nor have any related posts
+    let is_post: Vec<bool> = ctx
+        .posts
+        .iter()
+        .map(|(_, p)| p.post_type == PostType::Post)
+        .collect();
+    let index_by_path: HashMap<&Path, usize> = ctx
+        .posts
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| is_post[*i])
+        .map(|(i, (file, _))| (file.path.strip_prefix(&ctx.base_dir).unwrap(), i))
+        .collect();
+    let links: Vec<Vec<usize>> = linked_posts
+        .iter()
+        .zip(&is_post)
+        .map(|(paths, is_post)| {
+            paths
+                .iter()
+                .filter(|_| *is_post)
+                .filter_map(|path| index_by_path.get(path.as_path()).copied())
+                .collect()
+        })
+        .collect();
+    let tags: Vec<Vec<String>> = ctx
+        .posts
+        .iter()
+        .map(|(_, p)| match p.post_type {
+            PostType::Post => p.tags.clone(),
+            PostType::Page => vec![],
+        })
+        .collect();
+    let urls: Vec<String> = ctx
+        .posts
+        .iter()
+        .map(|(_, p)| p.relative_url.clone())
+        .collect();
+
+    let related = related::related_posts(&tags, &links, &urls, ctx.config.related_posts);
+    let links: Vec<PostLink> = ctx.posts.iter().map(|(_, p)| p.into()).collect();
+    for ((_, post), related) in ctx.posts.iter_mut().zip(related) {
+        post.related = related.into_iter().map(|i| links[i].clone()).collect();
+    }
+}
+
+/// The template for a post: the one in its frontmatter, its folder's from `folder-templates`, or
+/// `post.html` (`page.html` for pages).
+fn template(ctx: &GeneratorContext, file: &MarkdownFile) -> String {
+    if let Some(template) = &file.frontmatter.template {
+        return template.clone();
+    }
+
+    let path = file.path.strip_prefix(&ctx.base_dir).unwrap();
+    let folder_template = ctx
+        .config
+        .folder_templates
+        .iter()
+        .filter(|(folder, _)| path.starts_with(folder))
+        .max_by_key(|(folder, _)| folder.components().count());
+    match (folder_template, file.frontmatter.post_type) {
+        (Some((_, template)), _) => template.clone(),
+        (None, PostType::Post) => "post.html".into(),
+        (None, PostType::Page) => "page.html".into(),
+    }
+}
+
+fn generate_post(ctx: &GeneratorContext, file: &MarkdownFile, post_context: &PostContext) {
+    let file_dir = ctx.output_dir.join(file.slug(&ctx.base_dir));
+    let output_file = file_dir.join("index.html");
+
+    let mut context = ctx.global_context.clone();
+    context.insert("post", post_context);
 
     info!("Generating {}", file_dir.to_str().unwrap());
 
-    let rendered = ctx.tera.render("post.html", &context).unwrap();
+    let template = template(ctx, file);
+    let note = file
+        .path
+        .strip_prefix(&ctx.base_dir)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    if !ctx.tera.get_template_names().any(|t| t == template) {
+        error!(
+            "{} uses the template {}, which doesn't exist",
+            note, template
+        );
+        ::std::process::exit(1);
+    }
+    let rendered = match ctx.tera.render(&template, &context) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            error!("Could not render {} with {}: {:?}", note, template, e);
+            ::std::process::exit(1);
+        }
+    };
     fs::create_dir_all(&file_dir).expect("Failed to create directory");
     fs::write(output_file, rendered).expect("Failed to write output");
+}
 
-    for asset in assets {
-        let asset_source_path = &ctx.base_dir.join(&asset.source);
-        let asset_target_path = ctx.output_dir.join(&asset.target);
-        debug!("  Copying asset {}", asset_target_path.to_str().unwrap());
-        fs::copy(asset_source_path, asset_target_path).unwrap();
+/// Copies an asset unless an identical file was already published at the same path. Different
+/// files published at the same path are reported, keeping the first one.
+fn copy_asset(
+    ctx: &GeneratorContext,
+    asset: &Asset,
+    copied_assets: &mut HashMap<PathBuf, (String, PathBuf)>,
+) {
+    // the hash is only known if the file was read before, e.g. for `asset-paths: hashed`
+    let (hash, content) = match &asset.hash {
+        Some(hash) => (hash.clone(), None),
+        None => match fs::read(ctx.base_dir.join(&asset.source)) {
+            Ok(content) => (content_hash(&content), Some(content)),
+            Err(e) => {
+                warn!(
+                    "  Could not read asset {}: {}",
+                    asset.source.to_str().unwrap(),
+                    e
+                );
+                return;
+            }
+        },
+    };
+    match copied_assets.get(&asset.target) {
+        Some((copied_hash, _)) if copied_hash == &hash => {
+            debug!(
+                "  Skipping asset {}, already copied",
+                asset.target.to_str().unwrap()
+            );
+            return;
+        }
+        Some((_, copied_source)) => {
+            warn!(
+                "  Asset conflict: {} and {} are both published as {}, keeping the former",
+                copied_source.to_str().unwrap(),
+                asset.source.to_str().unwrap(),
+                asset.target.to_str().unwrap()
+            );
+            return;
+        }
+        None => {}
     }
 
-    return post_context;
+    let asset_target_path = ctx.output_dir.join(&asset.target);
+    debug!("  Copying asset {}", asset_target_path.to_str().unwrap());
+    fs::create_dir_all(asset_target_path.parent().unwrap()).expect("Failed to create directory");
+    match content {
+        Some(content) => fs::write(asset_target_path, content).expect("Failed to write asset"),
+        None => {
+            fs::copy(ctx.base_dir.join(&asset.source), asset_target_path)
+                .expect("Failed to copy asset");
+        }
+    }
+    copied_assets.insert(asset.target.clone(), (hash, asset.source.clone()));
 }
 
-fn generate_index_and_feed(ctx: &GeneratorContext) {
-    let posts: Vec<&PostContext> = ctx
+/// Builds the variables shared by all templates: the site's configuration, all posts and tags, and
+/// details about the build itself.
+fn global_context(ctx: &GeneratorContext) -> Context {
+    // this context is cloned for every page, so it leaves out each post's content, which
+    // `get_post` still returns
+    let (posts, pages): (Vec<&PostContext>, Vec<&PostContext>) = ctx
         .posts
         .iter()
         .map(|(_, post_context)| post_context)
+        .partition(|post_context| post_context.post_type == PostType::Post);
+    let without_content = |posts: Vec<&PostContext>| -> Vec<serde_json::Value> {
+        posts
+            .into_iter()
+            .map(|post| {
+                let mut value = serde_json::to_value(post).unwrap();
+                value.as_object_mut().unwrap().remove("markdown");
+                value
+            })
+            .collect()
+    };
+
+    let mut taxonomies = HashMap::new();
+    taxonomies.insert("tags", navigation::tags(ctx));
+
+    let mut build = HashMap::new();
+    build.insert("time", Utc::now().to_rfc3339());
+    build.insert("version", env!("CARGO_PKG_VERSION").to_string());
+
+    let feeds: Vec<HashMap<&str, String>> = ctx
+        .config
+        .feeds()
+        .iter()
+        .map(|feed| {
+            let mut link = HashMap::new();
+            let path = feed.path.to_str().unwrap().replace('\\', "/");
+            link.insert("url", format!("{}/{}", ctx.config.base_url, path));
+            link.insert("format", format!("{:?}", feed.format).to_lowercase());
+            link
+        })
         .collect();
+
     let mut context = Context::new();
-    context.insert("posts", &posts);
-    // TODO pass a global context around and extend sub-contexts from it
+    context.insert("config", &SiteConfig::from(&ctx.config));
+    context.insert("extra", &ctx.config.extra);
     context.insert("base_url", &ctx.config.base_url);
     context.insert("site_title", &ctx.config.title());
+    context.insert("posts", &without_content(posts));
+    context.insert("pages", &without_content(pages));
+    context.insert("taxonomies", &taxonomies);
+    context.insert("build", &build);
+    context.insert("feeds", &feeds);
+    if ctx.config.search {
+        // the search index's URL, e.g. for a search box
+        let path = ctx.config.search_path.to_str().unwrap().replace('\\', "/");
+        context.insert("search_index", &format!("{}/{}", ctx.config.base_url, path));
+    }
+    context
+}
 
-    let index = ctx.tera.render("index.html", &context).unwrap();
-    fs::write(&ctx.output_dir.join("index.html"), index).expect("Failed to write index");
+fn generate_index(ctx: &GeneratorContext) {
+    let index = ctx.tera.render("index.html", &ctx.global_context).unwrap();
+    fs::write(ctx.output_dir.join("index.html"), index).expect("Failed to write index");
+}
 
-    let feed = ctx.tera.render("feed.xml", &context);
-    let feed = match feed {
-        Ok(feed) => feed,
-        Err(_) => Tera::one_off(include_str!("templates/feed.xml"), &context, true).unwrap(),
-    };
-    fs::write(&ctx.output_dir.join(&ctx.config.feed_path), feed).expect("Failed to write feed");
+fn generate_not_found(ctx: &GeneratorContext) {
+    let not_found = ctx.tera.render("404.html", &ctx.global_context).unwrap();
+    fs::write(ctx.output_dir.join("404.html"), not_found).expect("Failed to write 404 page");
 }
 
-fn copy_static_files(ctx: &GeneratorContext) {
-    let source = &ctx.base_dir.join(".campfire").join("static");
-    if source.exists() {
-        info!(
-            "Copying static files from {}",
-            source.as_path().to_str().unwrap()
-        );
-        let copied_files = dir::copy_recursively(source, &ctx.output_dir);
-        info!("Copied {} files", copied_files);
+/// Renders a template from the vault's templates, or the built-in one if there's no such template.
+pub fn render_template(
+    ctx: &GeneratorContext,
+    name: &str,
+    default_template: &str,
+    context: &Context,
+) -> String {
+    if ctx.tera.get_template_names().any(|t| t == name) {
+        ctx.tera.render(name, context).unwrap()
     } else {
-        warn!("Not copying static files, directory doesn't exist");
+        Tera::one_off(default_template, context, !name.ends_with(".txt")).unwrap()
+    }
+}
+
+fn copy_static_files(ctx: &GeneratorContext) {
+    // the vault's static files are copied last, replacing the theme's
+    let mut sources = vec![];
+    if let Some(theme_dir) = theme::theme_dir(&ctx.base_dir, &ctx.config) {
+        sources.push(theme_dir.join("static"));
+    }
+    sources.push(ctx.base_dir.join(".campfire").join("static"));
+
+    for source in &sources {
+        if source.exists() {
+            info!(
+                "Copying static files from {}",
+                source.as_path().to_str().unwrap()
+            );
+            let copied_files = dir::copy_recursively(source, &ctx.output_dir);
+            info!("Copied {} files", copied_files);
+        } else {
+            info!(
+                "Not copying static files, {} doesn't exist",
+                source.as_path().to_str().unwrap()
+            );
+        }
     }
 }
 
@@ -185,16 +458,15 @@ fn run_post_build_command(ctx: &GeneratorContext, campfire_dir: PathBuf) {
     let post_build_command = &ctx
         .config
         .post_build_command
-        .replace("{{target}}", &ctx.output_dir.to_str().unwrap());
+        .replace("{{target}}", ctx.output_dir.to_str().unwrap());
     if !post_build_command.is_empty() {
         info!("Running post-build command: {}", post_build_command);
-        if cfg!(target_os = "windows") {
+        let mut child = if cfg!(target_os = "windows") {
             Command::new("cmd")
-                .args(&["/C", &post_build_command])
+                .args(["/C", post_build_command])
                 .current_dir(campfire_dir)
                 .stdout(Stdio::inherit())
                 .spawn()
-                .expect("failed to execute post-build command");
         } else {
             Command::new("sh")
                 .arg("-c")
@@ -202,7 +474,8 @@ fn run_post_build_command(ctx: &GeneratorContext, campfire_dir: PathBuf) {
                 .current_dir(campfire_dir)
                 .stdout(Stdio::inherit())
                 .spawn()
-                .expect("failed to execute post-build command");
         }
+        .expect("failed to execute post-build command");
+        child.wait().expect("failed to wait for post-build command");
     }
 }
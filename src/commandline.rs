@@ -1,7 +1,7 @@
 use clap::{command, AppSettings, Arg, Command, SubCommand};
 
 pub fn parse_command() -> Command<'static> {
-    return command!()
+    command!()
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .arg(
             Arg::with_name("base-directory")
@@ -19,5 +19,72 @@ pub fn parse_command() -> Command<'static> {
                 .takes_value(true)
                 .default_value(".campfire/campfire.yaml"),
         )
-        .subcommand(SubCommand::with_name("build").about("Builds the site"));
+        .arg(
+            Arg::with_name("base-url")
+                .long("base-url")
+                .help("URL the site is published at, instead of base-url in the configuration")
+                .takes_value(true),
+        )
+        .subcommand(SubCommand::with_name("build").about("Builds the site"))
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Creates the configuration, templates and static folders in a vault")
+                .arg(
+                    Arg::with_name("yes")
+                        .short('y')
+                        .long("yes")
+                        .help("Use the suggested values without asking"),
+                )
+                .arg(
+                    Arg::with_name("require-tag")
+                        .long("require-tag")
+                        .help("Only publish notes with this tag")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("new")
+                .about("Creates a note with its frontmatter filled in")
+                .arg(
+                    Arg::with_name("title")
+                        .help("Title of the note")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .help("Folder within the vault to create the note in")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .help("Tag the note, may be used several times")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::with_name("publish")
+                        .long("publish")
+                        .help("Tag the note with require-tag, so that it's published"),
+                )
+                .arg(
+                    Arg::with_name("archetype")
+                        .long("archetype")
+                        .help("Archetype in .campfire/archetypes to use, without .md")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists all notes, and whether they're published")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("Output format")
+                        .takes_value(true)
+                        .possible_values(["table", "json", "csv"])
+                        .default_value("table"),
+                ),
+        )
 }
@@ -1,10 +1,29 @@
-use serde::Deserialize;
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+/// Prefix of environment variables overriding the configuration, e.g. `CAMPFIRE_BASE_URL`.
+const ENV_PREFIX: &str = "CAMPFIRE_";
+/// Keys whose values may have any keys of their own, e.g. `extra.accent`.
+const FREE_FORM_KEYS: [&str; 3] = ["extra", "diagrams", "folder-templates"];
+/// Keys that still work, but have been replaced by another one.
+const DEPRECATED_KEYS: [(&str, &str); 1] = [("feed-path", "feeds")];
+
+lazy_static! {
+    static ref LOCATION_RE: Regex = Regex::new(r" at line \d+ column \d+").unwrap();
+    static ref UNKNOWN_RE: Regex =
+        Regex::new(r"unknown (field|variant) `([^`]*)`, expected (.*)").unwrap();
+    static ref EXPECTED_RE: Regex = Regex::new(r"`([^`]*)`").unwrap();
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub name: String,
     #[serde(default)]
@@ -20,25 +39,204 @@ pub struct Config {
     #[serde(rename = "feed-path", default = "default_feed_path")]
     pub feed_path: PathBuf,
     #[serde(default)]
+    feeds: Vec<Feed>,
+    /// The number of words in a post's summary if it has neither `<!-- more -->` nor a
+    /// `description`.
+    #[serde(rename = "summary-words", default = "default_summary_words")]
+    pub summary_words: usize,
+    /// The reading speed used to estimate a post's reading time.
+    #[serde(rename = "words-per-minute", default = "default_words_per_minute")]
+    pub words_per_minute: usize,
+    #[serde(default)]
     pub paths: Paths,
+    /// A theme in `.campfire/themes`, whose templates and static files the vault's own override.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Templates for all posts within a folder, e.g. `recipes: recipe.html`.
+    #[serde(rename = "folder-templates", default)]
+    pub folder_templates: HashMap<PathBuf, String>,
+    /// The language for `localized_date`, e.g. `de`.
+    #[serde(default = "default_locale")]
+    pub locale: String,
+    /// Anything else for templates to use.
+    #[serde(default)]
+    pub extra: HashMap<String, serde_json::Value>,
+    #[serde(default = "default_diagrams")]
+    pub diagrams: HashMap<String, String>,
+    #[serde(default)]
+    pub footnotes: FootnoteStyle,
+    #[serde(rename = "transclude-unpublished", default)]
+    pub transclude_unpublished: bool,
+    #[serde(default)]
+    pub images: Images,
+    #[serde(rename = "asset-paths", default)]
+    pub asset_paths: AssetPaths,
+    #[serde(rename = "link-paths", default)]
+    pub link_paths: LinkPaths,
+    #[serde(default = "default_true")]
+    pub sitemap: bool,
+    #[serde(default = "default_true")]
+    pub robots: bool,
+    #[serde(default)]
+    pub navigation: Navigation,
+    /// The number of related posts listed for each post.
+    #[serde(rename = "related-posts", default = "default_related_posts")]
+    pub related_posts: usize,
+    #[serde(default)]
+    pub search: bool,
+    #[serde(rename = "search-path", default = "default_search_path")]
+    pub search_path: PathBuf,
 }
 
 impl Config {
     pub fn title(&self) -> String {
-        if self.title != "" {
+        if !self.title.is_empty() {
             self.title.clone()
         } else {
             self.name.clone()
         }
     }
+
+    /// All feeds to generate, which is a single Atom feed at `feed.xml`, or the deprecated
+    /// `feed-path`, unless `feeds` are configured.
+    pub fn feeds(&self) -> Vec<Feed> {
+        if !self.feeds.is_empty() {
+            self.feeds.clone()
+        } else {
+            vec![Feed {
+                format: FeedFormat::Atom,
+                path: self.feed_path.clone(),
+                limit: None,
+                content: FeedContent::Full,
+            }]
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Feed {
+    #[serde(default)]
+    pub format: FeedFormat,
+    pub path: PathBuf,
+    /// The maximum number of posts within the feed, all posts if unset.
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub content: FeedContent,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    #[default]
+    Atom,
+    /// RSS 2.0
+    Rss,
+    /// JSON Feed 1.1
+    Json,
+}
+
+/// What's included of each post within a feed.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedContent {
+    /// The post's complete content.
+    #[default]
+    Full,
+    /// Only the post's summary, e.g. its first paragraph.
+    Summary,
+}
+
+/// Which posts are linked as a post's previous and next post.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Navigation {
+    /// The previous and next post by date.
+    #[default]
+    All,
+    /// The previous and next post within the same folder.
+    Section,
+    /// The previous and next post with the same first tag.
+    Tag,
+}
+
+/// Where footnotes are placed in a post.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FootnoteStyle {
+    /// A numbered list at the end of the post.
+    #[default]
+    Endnotes,
+    /// Tufte-style margin notes next to their reference.
+    Sidenotes,
+}
+
+/// How links within a note, e.g. `images/a.png`, are resolved. Links starting with `./` or `../`
+/// are always relative to the note.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPaths {
+    /// Relative to the note if such a file exists, otherwise relative to the vault.
+    #[default]
+    Auto,
+    /// Relative to the note, as Obsidian's "Relative path to file".
+    File,
+    /// Relative to the vault, as Obsidian's "Absolute path in vault".
+    Vault,
 }
 
-#[derive(Debug, Deserialize)]
+/// Where assets linked from posts, e.g. images, are copied to.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetPaths {
+    /// `static/<file name>`, shared by all posts.
+    #[default]
+    Flat,
+    /// `static/<file name>.<content hash>.<extension>`, shared by all posts.
+    Hashed,
+    /// `<post>/<file name>`, next to the post's `index.html`.
+    Post,
+}
+
+/// Resizing of images embedded in posts, disabled unless any `widths` are configured.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Images {
+    #[serde(default)]
+    pub widths: Vec<u32>,
+    #[serde(default = "default_image_sizes")]
+    pub sizes: String,
+    /// Additional formats to convert images to, e.g. `webp` or `avif`.
+    #[serde(default)]
+    pub formats: Vec<String>,
+    #[serde(rename = "strip-exif", default = "default_true")]
+    pub strip_exif: bool,
+    /// ImageMagick's executable, called as `<command> <input> -resize <width>x <output>`.
+    #[serde(default = "default_image_command")]
+    pub command: String,
+}
+
+impl Default for Images {
+    fn default() -> Self {
+        Images {
+            widths: vec![],
+            sizes: default_image_sizes(),
+            formats: vec![],
+            strip_exif: true,
+            command: default_image_command(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Paths {
     #[serde(default = "default_target_path")]
     pub target: PathBuf,
     #[serde(default = "default_template_path")]
     pub templates: PathBuf,
+    #[serde(default = "default_cache_path")]
+    pub cache: PathBuf,
 }
 
 impl Default for Paths {
@@ -46,6 +244,7 @@ impl Default for Paths {
         Paths {
             target: default_target_path(),
             templates: default_template_path(),
+            cache: default_cache_path(),
         }
     }
 }
@@ -58,11 +257,340 @@ fn default_target_path() -> PathBuf {
     PathBuf::from("out")
 }
 
+fn default_image_sizes() -> String {
+    "100vw".into()
+}
+
+fn default_image_command() -> String {
+    "magick".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_cache_path() -> PathBuf {
+    PathBuf::from("cache")
+}
+
 fn default_feed_path() -> PathBuf {
     PathBuf::from("feed.xml")
 }
 
-pub fn read_config(config_file: &PathBuf) -> Result<Config, Box<dyn Error>> {
-    let reader = BufReader::new(File::open(config_file)?);
-    Ok(serde_yaml::from_reader(reader)?)
+fn default_locale() -> String {
+    "en".into()
+}
+
+fn default_summary_words() -> usize {
+    50
+}
+
+fn default_words_per_minute() -> usize {
+    200
+}
+
+fn default_related_posts() -> usize {
+    5
+}
+
+fn default_search_path() -> PathBuf {
+    PathBuf::from("search_index.json")
+}
+
+/// Fenced code block languages rendered to SVG, with the command reading the diagram source from
+/// stdin and writing the SVG to stdout.
+fn default_diagrams() -> HashMap<String, String> {
+    let mut diagrams = HashMap::new();
+    diagrams.insert("mermaid".into(), "mmdc -i - -o - -e svg".into());
+    diagrams.insert("dot".into(), "dot -Tsvg".into());
+    diagrams.insert("plantuml".into(), "plantuml -tsvg -pipe".into());
+    diagrams
+}
+
+/// A value replacing the one in `campfire.yaml`, e.g. from an environment variable.
+pub struct Override {
+    /// The key, with `.` between nested keys, e.g. `paths.target`.
+    pub key: String,
+    pub value: Value,
+    /// Where the value came from, e.g. `--base-url`.
+    pub source: String,
+}
+
+/// Overrides from `CAMPFIRE_*` environment variables, e.g. `CAMPFIRE_BASE_URL` for `base-url` or
+/// `CAMPFIRE_PATHS__TARGET` for `paths.target`. Variables which don't match any key, e.g.
+/// `CAMPFIRE_HOME`, are ignored with a warning once the configuration is read.
+pub fn env_overrides() -> Vec<Override> {
+    let mut overrides: Vec<Override> = std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some(Override {
+                key: env_key(key),
+                value: Value::String(value),
+                source: name.clone(),
+            })
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.source.cmp(&b.source));
+    overrides
+}
+
+/// Why `campfire.yaml` couldn't be read, with the line and column if known.
+#[derive(Debug)]
+pub struct ConfigError {
+    file: PathBuf,
+    location: Option<(usize, usize)>,
+    message: String,
+}
+
+impl ConfigError {
+    fn new(file: &Path, message: String) -> Self {
+        ConfigError {
+            file: file.into(),
+            location: None,
+            message,
+        }
+    }
+
+    fn from_yaml(file: &Path, error: serde_yaml::Error) -> Self {
+        ConfigError {
+            file: file.into(),
+            location: error
+                .location()
+                .map(|location| (location.line(), location.column())),
+            message: explain(&LOCATION_RE.replace_all(&error.to_string(), "")),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.to_str().unwrap())?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Turns an environment variable without its prefix into a key, e.g. `PATHS__TARGET` into
+/// `paths.target`. Keys within free-form keys are kept as written, e.g. `EXTRA__my_key` sets
+/// `extra.my_key`.
+fn env_key(name: &str) -> String {
+    let config_key = |part: &str| part.to_lowercase().replace('_', "-");
+    let mut parts = name.split("__");
+    let first = config_key(parts.next().unwrap_or_default());
+    let free_form = FREE_FORM_KEYS.contains(&first.as_str());
+    let rest = parts.map(|part| {
+        if free_form {
+            part.to_string()
+        } else {
+            config_key(part)
+        }
+    });
+    std::iter::once(first)
+        .chain(rest)
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// Reads and validates the configuration, then applies `overrides` in order. Overrides for keys
+/// the configuration doesn't have are ignored with a warning. String values for keys that aren't
+/// strings are parsed as YAML, so `CAMPFIRE_SEARCH=true` is a boolean, while
+/// `CAMPFIRE_REQUIRE_TAG=2022` stays a string.
+pub fn read_config(config_file: &Path, overrides: &[Override]) -> Result<Config, ConfigError> {
+    let content = fs::read_to_string(config_file)
+        .map_err(|e| ConfigError::new(config_file, format!("Could not read config: {}", e)))?;
+    let mut config: Config =
+        serde_yaml::from_str(&content).map_err(|e| ConfigError::from_yaml(config_file, e))?;
+    warn_deprecated_keys(config_file, &content);
+
+    for o in overrides {
+        let mut value = serde_yaml::to_value(&config).unwrap();
+        let free_form = FREE_FORM_KEYS.contains(&o.key.split('.').next().unwrap_or_default());
+        let keep_string = match get_value(&value, &o.key) {
+            Some(Value::String(_)) | Some(Value::Null) => true,
+            Some(_) => false,
+            None if free_form => false,
+            None => {
+                warn!("Ignoring {}, there's no `{}` to configure", o.source, o.key);
+                continue;
+            }
+        };
+        let new_value = match &o.value {
+            Value::String(s) if !keep_string => {
+                serde_yaml::from_str(s).unwrap_or_else(|_| o.value.clone())
+            }
+            _ => o.value.clone(),
+        };
+        set_value(&mut value, &o.key, new_value);
+        config = serde_yaml::from_value(value).map_err(|e| {
+            let error = ConfigError::from_yaml(config_file, e);
+            ConfigError::new(
+                config_file,
+                format!("{} (from {})", error.message, o.source),
+            )
+        })?;
+    }
+    Ok(config)
+}
+
+/// Returns the value at a key with `.` between nested keys, if there is one.
+fn get_value<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(value, |current, part| current.get(part))
+}
+
+/// Sets a value, with `.` between nested keys.
+fn set_value(value: &mut Value, key: &str, new_value: Value) {
+    let (key, nested_key) = match key.split_once('.') {
+        Some((key, nested_key)) => (key, Some(nested_key)),
+        None => (key, None),
+    };
+    if !value.is_mapping() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    let mapping = value.as_mapping_mut().unwrap();
+    let key = Value::String(key.into());
+    match nested_key {
+        Some(nested_key) => {
+            let nested = mapping.entry(key).or_insert(Value::Null);
+            set_value(nested, nested_key, new_value)
+        }
+        None => {
+            mapping.insert(key, new_value);
+        }
+    }
+}
+
+fn warn_deprecated_keys(config_file: &Path, content: &str) {
+    for (key, replacement) in DEPRECATED_KEYS {
+        let prefix = format!("{}:", key);
+        if let Some(line) = content.lines().position(|line| line.starts_with(&prefix)) {
+            warn!(
+                "{}:{}: `{}` is deprecated, use `{}` instead",
+                config_file.to_str().unwrap(),
+                line + 1,
+                key,
+                replacement
+            );
+        }
+    }
+}
+
+/// Replaces the list of expected names in serde's "unknown field" and "unknown variant" errors
+/// with the closest one, if any is close enough.
+fn explain(message: &str) -> String {
+    let captures = match UNKNOWN_RE.captures(message) {
+        Some(captures) => captures,
+        None => return message.into(),
+    };
+    let unknown = &captures[2];
+    let suggestion = EXPECTED_RE
+        .captures_iter(&captures[3])
+        .map(|expected| expected[1].to_string())
+        .map(|expected| (strsim::levenshtein(unknown, &expected), expected))
+        .filter(|(distance, _)| *distance <= 3)
+        .min();
+    match suggestion {
+        Some((_, suggestion)) => format!(
+            "{}unknown {} `{}`, did you mean `{}`?",
+            &message[..captures.get(0).unwrap().start()],
+            &captures[1],
+            unknown,
+            suggestion
+        ),
+        None => message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env_key, read_config, set_value, Override};
+    use serde_yaml::Value;
+    use std::fs;
+
+    #[test]
+    fn suggest_unknown_fields() {
+        let file = std::env::temp_dir().join("campfire-config-test.yaml");
+        fs::write(
+            &file,
+            "name: blog\nrequire-tag: published\nbase_url: http://localhost\n",
+        )
+        .unwrap();
+        let error = read_config(&file, &[]).unwrap_err().to_string();
+        assert!(error.ends_with(":3:1: unknown field `base_url`, did you mean `base-url`?"));
+
+        fs::write(
+            &file,
+            "name: blog\nrequire-tag: published\nfeeds:\n  - format: rs\n    path: a.xml\n",
+        )
+        .unwrap();
+        let error = read_config(&file, &[]).unwrap_err().to_string();
+        assert!(error.ends_with(": feeds[0].format: unknown variant `rs`, did you mean `rss`?"));
+
+        fs::write(&file, "name: blog\nrequire-tag: published\n").unwrap();
+        let overrides = vec![
+            Override {
+                key: "base-url".into(),
+                value: Value::String("https://example.com".into()),
+                source: "CAMPFIRE_BASE_URL".into(),
+            },
+            Override {
+                key: "paths.target".into(),
+                value: Value::String("public".into()),
+                source: "CAMPFIRE_PATHS__TARGET".into(),
+            },
+        ];
+        let config = read_config(&file, &overrides).unwrap();
+        assert_eq!(config.base_url, "https://example.com");
+        assert_eq!(config.paths.target.to_str().unwrap(), "public");
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn override_known_keys_only() {
+        let file = std::env::temp_dir().join("campfire-config-override-test.yaml");
+        fs::write(&file, "name: blog\nrequire-tag: published\n").unwrap();
+        let env = |key: &str, value: &str| Override {
+            key: key.into(),
+            value: Value::String(value.into()),
+            source: format!("CAMPFIRE_{}", key.to_uppercase()),
+        };
+        let overrides = vec![
+            env("home", "/home/blog"),
+            env("require-tag", "2022"),
+            env("search", "true"),
+            env("summary-words", "30"),
+            env("extra.accent", "5"),
+        ];
+        let config = read_config(&file, &overrides).unwrap();
+        assert_eq!(config.require_tag, "2022");
+        assert!(config.search);
+        assert_eq!(config.summary_words, 30);
+        assert_eq!(config.extra["accent"], serde_json::json!(5));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn env_keys() {
+        assert_eq!(env_key("BASE_URL"), "base-url");
+        assert_eq!(env_key("PATHS__TARGET"), "paths.target");
+        assert_eq!(env_key("EXTRA__my_key"), "extra.my_key");
+        assert_eq!(
+            env_key("FOLDER_TEMPLATES__Daily_Notes"),
+            "folder-templates.Daily_Notes"
+        );
+    }
+
+    #[test]
+    fn set_nested_value() {
+        let mut value: Value = serde_yaml::from_str("a: 1").unwrap();
+        set_value(&mut value, "b.c", Value::Bool(true));
+        assert_eq!(
+            serde_yaml::to_string(&value).unwrap(),
+            "a: 1\nb:\n  c: true\n"
+        );
+    }
 }
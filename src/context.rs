@@ -1,23 +1,122 @@
 use crate::config::Config;
-use crate::markdown::MarkdownFile;
+use crate::diagram::Diagrams;
+use crate::markdown::{MarkdownFile, PostType};
 use serde::Serialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use tera::Tera;
+use std::sync::Mutex;
+use tera::{Context, Tera};
 
 #[derive(Serialize, Clone)]
 pub struct PostContext {
     pub title: String,
+    #[serde(rename = "type")]
+    pub post_type: PostType,
     pub tags: Vec<String>,
     pub author: String,
     pub original_file_name: String,
     pub relative_url: String,
 
-    pub date: String,
-    pub year: i32,
-    pub month: u32,
-    pub day: u32,
+    /// Only pages may have no date.
+    pub date: Option<String>,
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    /// When the post was last changed, which is its `date` unless there's an `updated` date.
+    pub updated: Option<String>,
 
     pub markdown: String,
+    /// The post's content before `<!-- more -->`, or its `description` or first words.
+    pub summary: String,
+    #[serde(flatten)]
+    pub statistics: PostStatistics,
+    /// Other posts sharing tags or links with this one, most closely related first.
+    pub related: Vec<PostLink>,
+    /// The next older post.
+    pub previous: Option<PostLink>,
+    /// The next newer post.
+    pub next: Option<PostLink>,
+    pub series: Option<SeriesContext>,
+}
+
+/// Posts sharing the same `series` in their frontmatter.
+#[derive(Serialize, Clone)]
+pub struct SeriesContext {
+    pub name: String,
+    /// The series' landing page.
+    pub relative_url: String,
+    /// The post's position within the series, starting at 1.
+    pub index: usize,
+    /// All posts in the series, from oldest to newest.
+    pub parts: Vec<PostLink>,
+}
+
+/// A tag and all posts with it.
+#[derive(Serialize)]
+pub struct TagContext {
+    pub name: String,
+    /// The tag's page, listing all its posts.
+    pub relative_url: String,
+    pub posts: Vec<PostLink>,
+}
+
+/// A reference to another post, e.g. to link to it.
+#[derive(Serialize, Clone)]
+pub struct PostLink {
+    pub title: String,
+    pub relative_url: String,
+    pub date: String,
+}
+
+impl From<&PostContext> for PostLink {
+    fn from(post: &PostContext) -> Self {
+        PostLink {
+            title: post.title.clone(),
+            relative_url: post.relative_url.clone(),
+            // only posts, which always have a date, are linked to
+            date: post.date.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct PostStatistics {
+    pub word_count: usize,
+    /// In minutes, based on `words-per-minute`.
+    pub reading_time: usize,
+    pub image_count: usize,
+    pub code_block_count: usize,
+    pub footnote_count: usize,
+}
+
+/// The configuration as templates see it, without e.g. the `post-build` and `diagrams` commands,
+/// which shouldn't end up on the published site.
+#[derive(Serialize)]
+pub struct SiteConfig<'a> {
+    pub name: &'a str,
+    pub title: String,
+    pub author: &'a str,
+    #[serde(rename = "base-url")]
+    pub base_url: &'a str,
+    pub locale: &'a str,
+    pub theme: Option<&'a str>,
+    pub search: bool,
+    pub extra: &'a HashMap<String, serde_json::Value>,
+}
+
+impl<'a> From<&'a Config> for SiteConfig<'a> {
+    fn from(config: &'a Config) -> Self {
+        SiteConfig {
+            name: &config.name,
+            title: config.title(),
+            author: &config.author,
+            base_url: &config.base_url,
+            locale: &config.locale,
+            theme: config.theme.as_deref(),
+            search: config.search,
+            extra: &config.extra,
+        }
+    }
 }
 
 pub struct GeneratorContext {
@@ -25,5 +124,32 @@ pub struct GeneratorContext {
     pub tera: Tera,
     pub base_dir: PathBuf,
     pub output_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub diagrams: Diagrams,
+    /// All notes within the vault, including unpublished ones.
+    pub notes: Vec<PathBuf>,
     pub posts: Vec<(MarkdownFile, PostContext)>,
+    /// Variables available to every template, which each page extends.
+    pub global_context: Context,
+    /// The file published at each path with `asset-paths: flat`, to detect different files with
+    /// the same name.
+    pub flat_assets: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+/// A context for a vault within the temporary directory, without any notes or templates.
+#[cfg(test)]
+pub fn test_context(config: &str) -> GeneratorContext {
+    let base_dir = std::env::temp_dir().join("campfire-test-vault");
+    GeneratorContext {
+        config: serde_yaml::from_str(config).unwrap(),
+        tera: Tera::default(),
+        output_dir: base_dir.join("out"),
+        cache_dir: base_dir.join("cache"),
+        diagrams: Diagrams::new(&HashMap::new(), base_dir.join("cache")),
+        base_dir,
+        notes: vec![],
+        posts: vec![],
+        global_context: Context::new(),
+        flat_assets: Default::default(),
+    }
 }
@@ -3,15 +3,15 @@ use std::fmt;
 use std::marker::PhantomData;
 
 pub mod utc_date {
-    use chrono::{Date, NaiveDate, Utc};
+    use chrono::NaiveDate;
     use serde::{Deserialize, Deserializer};
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Date<Utc>>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
     where
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let parsed = s.parse::<NaiveDate>().map(|s| Date::from_utc(s, Utc));
+        let parsed = s.parse::<NaiveDate>();
         match parsed {
             Ok(p) => Ok(Some(p)),
             Err(_) => Ok(None),
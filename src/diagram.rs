@@ -0,0 +1,302 @@
+use crate::hash::hash_parts;
+use log::{debug, warn};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a diagram renderer may run before it's stopped, so that a hanging tool doesn't stall
+/// the build.
+pub const RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Turns the source of a diagram into an SVG image.
+pub trait DiagramRenderer: Send + Sync {
+    /// Returns the rendered SVG, or `None` if the diagram couldn't be rendered.
+    fn render(&self, source: &str) -> Option<String>;
+
+    /// Identifies the renderer's output, so that changing it invalidates cached diagrams.
+    fn cache_key(&self) -> String;
+}
+
+/// Renders diagrams by piping their source through a local executable, e.g. `dot -Tsvg`. The
+/// command is split like a shell would, so arguments with spaces can be quoted.
+pub struct CommandRenderer {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl DiagramRenderer for CommandRenderer {
+    fn render(&self, source: &str) -> Option<String> {
+        let args = match shell_words::split(&self.command) {
+            Ok(args) => args,
+            Err(e) => {
+                warn!("Could not parse diagram renderer '{}': {}", self.command, e);
+                return None;
+            }
+        };
+        let (program, args) = args.split_first()?;
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("Could not run diagram renderer '{}': {}", program, e);
+                return None;
+            }
+        };
+
+        // stdin is written and stdout and stderr are read on their own threads, as a renderer
+        // may not read all of its input before its output fills up the pipe
+        let mut stdin = child.stdin.take().unwrap();
+        let source = source.to_string();
+        thread::spawn(move || stdin.write_all(source.as_bytes()));
+        let stdout = read_in_background(child.stdout.take().unwrap());
+        let stderr = read_in_background(child.stderr.take().unwrap());
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if started.elapsed() < self.timeout => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Ok(None) => {
+                    warn!(
+                        "Diagram renderer '{}' didn't finish within {:?}",
+                        program, self.timeout
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return None;
+                }
+                Err(e) => {
+                    warn!("Could not wait for diagram renderer '{}': {}", program, e);
+                    return None;
+                }
+            }
+        };
+        let stdout = stdout.join().ok()?;
+        if !status.success() {
+            let stderr = stderr.join().unwrap_or_default();
+            warn!(
+                "Diagram renderer '{}' failed: {}",
+                program,
+                String::from_utf8_lossy(&stderr).trim()
+            );
+            return None;
+        }
+
+        // Some tools prefix the SVG with an XML declaration or doctype, which can't be inlined
+        let svg = String::from_utf8(stdout).ok()?;
+        let start = svg.find("<svg")?;
+        Some(svg[start..].trim_end().into())
+    }
+
+    fn cache_key(&self) -> String {
+        self.command.clone()
+    }
+}
+
+/// All configured diagram renderers, keyed by the language of the fenced code block.
+pub struct Diagrams {
+    pub renderers: HashMap<String, Box<dyn DiagramRenderer>>,
+    pub cache_dir: PathBuf,
+}
+
+impl Diagrams {
+    pub fn new(commands: &HashMap<String, String>, cache_dir: PathBuf) -> Diagrams {
+        let mut renderers: HashMap<String, Box<dyn DiagramRenderer>> = HashMap::new();
+        for (language, command) in commands {
+            if !command.trim().is_empty() {
+                renderers.insert(
+                    language.clone(),
+                    Box::new(CommandRenderer {
+                        command: command.clone(),
+                        timeout: RENDER_TIMEOUT,
+                    }),
+                );
+            }
+        }
+        Diagrams {
+            renderers,
+            cache_dir,
+        }
+    }
+
+    /// Replaces fenced code blocks in a supported language with their inline SVG. Code blocks
+    /// that can't be rendered are kept as they are.
+    pub fn render_code_blocks<'a>(
+        &self,
+        events: impl Iterator<Item = Event<'a>>,
+    ) -> Vec<Event<'a>> {
+        let mut result = Vec::new();
+        let mut code_block: Option<(String, Vec<Event<'a>>)> = None;
+        for event in events {
+            match (&mut code_block, event) {
+                (None, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))))
+                    if self.renderers.contains_key(fence_language(&info)) =>
+                {
+                    let language = fence_language(&info).to_string();
+                    let start = Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)));
+                    code_block = Some((language, vec![start]));
+                }
+                (Some((language, buffered)), Event::End(Tag::CodeBlock(kind))) => {
+                    let source: String = buffered
+                        .iter()
+                        .filter_map(|e| match e {
+                            Event::Text(text) => Some(text.as_ref()),
+                            _ => None,
+                        })
+                        .collect();
+                    match self.render(language, &source) {
+                        Some(svg) => result.push(Event::Html(CowStr::from(format!(
+                            "<figure class=\"diagram diagram-{}\">{}</figure>\n",
+                            language, svg
+                        )))),
+                        None => {
+                            result.append(buffered);
+                            result.push(Event::End(Tag::CodeBlock(kind)));
+                        }
+                    }
+                    code_block = None;
+                }
+                (Some((_, buffered)), event) => buffered.push(event),
+                (None, event) => result.push(event),
+            }
+        }
+        result
+    }
+
+    /// Renders a single diagram, reusing the cached SVG if the same source was rendered before.
+    fn render(&self, language: &str, source: &str) -> Option<String> {
+        let renderer = self.renderers.get(language)?;
+
+        let hash = hash_parts(&[renderer.cache_key().as_bytes(), source.as_bytes()]);
+        let cache_file = self
+            .cache_dir
+            .join("diagrams")
+            .join(format!("{}.svg", hash));
+        if let Ok(svg) = fs::read_to_string(&cache_file) {
+            debug!("  Using cached diagram {}", cache_file.to_str().unwrap());
+            return Some(svg);
+        }
+
+        let svg = renderer.render(source)?;
+        if fs::create_dir_all(cache_file.parent().unwrap()).is_ok() {
+            if let Err(e) = fs::write(&cache_file, &svg) {
+                warn!("Could not cache diagram: {}", e);
+            }
+        }
+        Some(svg)
+    }
+}
+
+/// Reads everything from a pipe on a separate thread.
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
+
+/// The language of a fenced code block, which is the first word of its info string, e.g. `mermaid`
+/// for ```` ```mermaid title="Flow" ````.
+fn fence_language(info: &str) -> &str {
+    info.split_whitespace().next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandRenderer, DiagramRenderer, Diagrams};
+    use pulldown_cmark::{html, Parser};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    struct FakeRenderer(Option<&'static str>);
+
+    impl DiagramRenderer for FakeRenderer {
+        fn render(&self, _source: &str) -> Option<String> {
+            self.0.map(|svg| svg.into())
+        }
+
+        fn cache_key(&self) -> String {
+            "fake".into()
+        }
+    }
+
+    fn render(renderer: FakeRenderer, markdown: &str, test_name: &str) -> String {
+        let cache_dir = std::env::temp_dir().join(format!("campfire-{}", test_name));
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        let mut renderers: HashMap<String, Box<dyn DiagramRenderer>> = HashMap::new();
+        renderers.insert("dot".into(), Box::new(renderer));
+        let diagrams = Diagrams {
+            renderers,
+            cache_dir,
+        };
+        let mut dest = String::new();
+        html::push_html(
+            &mut dest,
+            diagrams
+                .render_code_blocks(Parser::new(markdown))
+                .into_iter(),
+        );
+        dest
+    }
+
+    #[test]
+    fn inlines_rendered_svg() {
+        let html = render(
+            FakeRenderer(Some("<svg></svg>")),
+            "```dot title\ndigraph { a -> b }\n```\n\n```rust\nfn main() {}\n```",
+            "inlines_rendered_svg",
+        );
+        assert_eq!(
+            html,
+            "<figure class=\"diagram diagram-dot\"><svg></svg></figure>\n\
+             <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn keeps_code_block_if_rendering_fails() {
+        let html = render(
+            FakeRenderer(None),
+            "```dot\ndigraph { a -> b }\n```",
+            "keeps_code_block_if_rendering_fails",
+        );
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-dot\">digraph { a -&gt; b }\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pipes_large_diagrams_and_stops_hanging_renderers() {
+        let source = format!("<svg>{}</svg>", "x".repeat(1 << 20));
+        let cat = CommandRenderer {
+            command: "cat".into(),
+            timeout: Duration::from_secs(10),
+        };
+        assert_eq!(cat.render(&source), Some(source.clone()));
+
+        let sleep = CommandRenderer {
+            command: "sleep 10".into(),
+            timeout: Duration::from_millis(100),
+        };
+        let started = Instant::now();
+        assert_eq!(sleep.render(&source), None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
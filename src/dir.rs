@@ -1,67 +1,145 @@
 use crate::config::Config;
-use crate::markdown::{read_markdown_file, MarkdownFile};
-use log::debug;
+use crate::markdown::{read_markdown_file, Frontmatter, MarkdownFile, PostType};
+use log::{debug, warn};
+use serde::Serialize;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 
 pub fn find_all_markdown_files(base_directory: &Path, config: &Config) -> Vec<MarkdownFile> {
-    let mut markdown_files = Vec::new();
-    for entry in base_directory.read_dir().expect("Could not read directory") {
-        if let Ok(entry) = entry {
-            let file_name = entry.file_name().into_string().unwrap();
-            if file_name.starts_with(".") || file_name.starts_with("_") {
-                continue;
-            }
-
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_dir() {
-                    markdown_files.append(&mut find_all_markdown_files(&entry.path(), &config));
-                } else if file_name.ends_with(".md") {
-                    let markdown_file = read_markdown_file(entry.path().clone());
-                    if let Some(markdown_file) = markdown_file {
-                        if is_allowed(&markdown_file, &config) {
-                            markdown_files.push(markdown_file);
-                        }
-                    }
+    find_all_note_paths(base_directory)
+        .into_iter()
+        .filter_map(read_markdown_file)
+        .filter(
+            |markdown_file| match exclusion(&markdown_file.frontmatter, config) {
+                Some(Exclusion::NoDate) => {
+                    warn!(
+                        "Skipping {}, posts need a date",
+                        markdown_file.path.to_str().unwrap()
+                    );
+                    false
                 }
-            } else {
-                panic!("Couldn't get file type for {:?}", entry.path())
+                Some(_) => false,
+                None => true,
+            },
+        )
+        .collect()
+}
+
+/// Finds all markdown files, published or not, skipping hidden and `_`-prefixed entries.
+pub fn find_all_note_paths(base_directory: &Path) -> Vec<PathBuf> {
+    find_markdown_paths(base_directory, false)
+}
+
+/// Finds all markdown files, including those within hidden and `_`-prefixed entries.
+pub fn find_all_markdown_paths(base_directory: &Path) -> Vec<PathBuf> {
+    find_markdown_paths(base_directory, true)
+}
+
+/// Whether a file or folder isn't published, since its name starts with `.` or `_`.
+fn is_skipped_name(name: &str) -> bool {
+    name.starts_with('.') || name.starts_with('_')
+}
+
+/// Whether a path relative to the vault isn't published, since it's within a hidden or
+/// `_`-prefixed folder, e.g. `.obsidian/` or `_private/`, or its own name starts with `.` or `_`.
+pub fn is_skipped(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().to_str().is_some_and(is_skipped_name))
+}
+
+fn find_markdown_paths(directory: &Path, include_skipped: bool) -> Vec<PathBuf> {
+    let mut note_paths = Vec::new();
+    for entry in directory
+        .read_dir()
+        .expect("Could not read directory")
+        .flatten()
+    {
+        let file_name = entry.file_name().into_string().unwrap();
+        if !include_skipped && is_skipped_name(&file_name) {
+            continue;
+        }
+
+        if let Ok(file_type) = entry.file_type() {
+            if file_type.is_dir() {
+                note_paths.append(&mut find_markdown_paths(&entry.path(), include_skipped));
+            } else if file_name.ends_with(".md") {
+                note_paths.push(entry.path());
             }
+        } else {
+            panic!("Couldn't get file type for {:?}", entry.path())
         }
     }
 
-    markdown_files
+    note_paths
+}
+
+/// Why a note isn't published.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Exclusion {
+    /// The note isn't tagged with `require-tag`.
+    MissingTag,
+    /// The note has `draft: true` in its frontmatter.
+    Draft,
+    /// The note is a post, but has no valid `date`.
+    NoDate,
+    /// The note is within a hidden or `_`-prefixed folder, or its name starts with `.` or `_`.
+    SkippedFolder,
+    NoFrontmatter,
+    /// The note's frontmatter isn't valid YAML, or lacks `tags`.
+    Unparsable,
 }
 
-fn is_allowed(file: &MarkdownFile, config: &Config) -> bool {
-    file.frontmatter.tags.contains(&config.require_tag)
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Exclusion::MissingTag => "missing tag",
+            Exclusion::Draft => "draft",
+            Exclusion::NoDate => "no date",
+            Exclusion::SkippedFolder => "skipped folder",
+            Exclusion::NoFrontmatter => "no frontmatter",
+            Exclusion::Unparsable => "unparsable",
+        })
+    }
+}
+
+/// Why a note with the given frontmatter isn't published, if it isn't.
+pub fn exclusion(frontmatter: &Frontmatter, config: &Config) -> Option<Exclusion> {
+    if !frontmatter.tags.contains(&config.require_tag) {
+        Some(Exclusion::MissingTag)
+    } else if frontmatter.draft {
+        Some(Exclusion::Draft)
+    } else if frontmatter.post_type == PostType::Post && frontmatter.date.is_none() {
+        Some(Exclusion::NoDate)
+    } else {
+        None
+    }
 }
 
-pub fn copy_recursively(source: &PathBuf, target: &PathBuf) -> u32 {
+pub fn copy_recursively(source: &Path, target: &Path) -> u32 {
     if !target.exists() {
         fs::create_dir(target).unwrap();
     }
 
     let mut count: u32 = 0;
-    for file in source.read_dir().unwrap() {
-        if let Ok(entry) = file {
-            if let Ok(file_type) = entry.file_type() {
-                let source_file = source.join(entry.file_name());
-                let target_file = target.join(entry.file_name());
-                if file_type.is_dir() {
-                    count += copy_recursively(&source_file, &target_file);
-                } else {
-                    debug!(
-                        "Copying {} to {}",
-                        &source_file.to_str().unwrap(),
-                        &target_file.to_str().unwrap()
-                    );
-                    fs::copy(source_file, target_file).unwrap();
-                    count += 1;
-                }
+    for entry in source.read_dir().unwrap().flatten() {
+        if let Ok(file_type) = entry.file_type() {
+            let source_file = source.join(entry.file_name());
+            let target_file = target.join(entry.file_name());
+            if file_type.is_dir() {
+                count += copy_recursively(&source_file, &target_file);
             } else {
-                panic!("Couldn't get file type for {:?}", entry.path())
+                debug!(
+                    "Copying {} to {}",
+                    &source_file.to_str().unwrap(),
+                    &target_file.to_str().unwrap()
+                );
+                fs::copy(source_file, target_file).unwrap();
+                count += 1;
             }
+        } else {
+            panic!("Couldn't get file type for {:?}", entry.path())
         }
     }
 
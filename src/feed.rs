@@ -0,0 +1,92 @@
+use crate::build::render_template;
+use crate::config::{Feed, FeedContent, FeedFormat};
+use crate::context::{GeneratorContext, PostContext};
+use crate::markdown::PostType;
+use regex::{Captures, Regex};
+use std::fs;
+
+lazy_static! {
+    static ref URL_ATTRIBUTE: Regex = Regex::new(r#"(href|src)="([^"]*)""#).unwrap();
+}
+
+/// Writes all configured feeds.
+pub fn generate_feeds(ctx: &GeneratorContext) {
+    for feed in ctx.config.feeds() {
+        generate_feed(ctx, &feed);
+    }
+}
+
+fn generate_feed(ctx: &GeneratorContext, feed: &Feed) {
+    let posts: Vec<PostContext> = ctx
+        .posts
+        .iter()
+        .filter(|(_, post)| post.post_type == PostType::Post)
+        .take(feed.limit.unwrap_or(usize::MAX))
+        .map(|(_, post)| {
+            let post_url = format!("{}/{}", ctx.config.base_url, post.relative_url);
+            PostContext {
+                markdown: absolute_urls(&post.markdown, &ctx.config.base_url, &post_url),
+                summary: absolute_urls(&post.summary, &ctx.config.base_url, &post_url),
+                ..post.clone()
+            }
+        })
+        .collect();
+    let updated = posts.iter().filter_map(|post| post.updated.clone()).max();
+    let feed_url = format!(
+        "{}/{}",
+        ctx.config.base_url,
+        feed.path.to_str().unwrap().replace('\\', "/")
+    );
+
+    let mut context = ctx.global_context.clone();
+    context.insert("posts", &posts);
+    context.insert("feed_url", &feed_url);
+    context.insert("updated", &updated.unwrap_or_else(|| "1970-01-01".into()));
+    context.insert("summary_only", &(feed.content == FeedContent::Summary));
+
+    let (template, default_template) = match feed.format {
+        FeedFormat::Atom => ("feed.xml", include_str!("templates/feed.xml")),
+        FeedFormat::Rss => ("rss.xml", include_str!("templates/rss.xml")),
+        FeedFormat::Json => ("feed.json", include_str!("templates/feed.json")),
+    };
+    let rendered = render_template(ctx, template, default_template, &context);
+    let output_file = ctx.output_dir.join(&feed.path);
+    fs::create_dir_all(output_file.parent().unwrap()).expect("Failed to create directory");
+    fs::write(output_file, rendered).expect("Failed to write feed");
+}
+
+/// Rewrites relative URLs within a post's HTML, e.g. links to footnotes, so that they still work
+/// when the post is read in a feed reader.
+fn absolute_urls(html: &str, base_url: &str, post_url: &str) -> String {
+    URL_ATTRIBUTE
+        .replace_all(html, |cap: &Captures| {
+            let url = &cap[2];
+            let absolute = if url.starts_with('#') {
+                format!("{}{}", post_url, url)
+            } else if url.starts_with('/') && !url.starts_with("//") {
+                format!("{}{}", base_url, url)
+            } else if url.contains(':') || url.starts_with("//") {
+                url.to_string()
+            } else {
+                format!("{}{}", post_url, url)
+            };
+            format!("{}=\"{}\"", &cap[1], absolute)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::absolute_urls;
+
+    #[test]
+    fn rewrite_relative_urls() {
+        let html = "<a href=\"#fn-1\">[1]</a> <img src=\"/static/a.png\" /> \
+                    <a href=\"https://example.com/\">x</a> <a href=\"other/\">y</a>";
+        assert_eq!(
+            absolute_urls(html, "https://blog", "https://blog/post/"),
+            "<a href=\"https://blog/post/#fn-1\">[1]</a> <img src=\"https://blog/static/a.png\" /> \
+             <a href=\"https://example.com/\">x</a> <a href=\"https://blog/post/other/\">y</a>"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use regex::Regex;
+use std::collections::HashSet;
+use std::ops::Range;
+
+lazy_static! {
+    static ref DEFINITION: Regex = Regex::new(r"^ {0,3}\[\^([^\]\s]+)\]:[ \t]?(.*)$").unwrap();
+    static ref LABEL: Regex = Regex::new(r"\[\^([^\]\s]+)\]").unwrap();
+}
+
+/// Generates labels for inline footnotes, skipping any label the note uses itself.
+struct InlineLabels {
+    used: HashSet<String>,
+    next: usize,
+}
+
+impl InlineLabels {
+    fn new(markdown: &str) -> Self {
+        InlineLabels {
+            used: LABEL
+                .captures_iter(markdown)
+                .map(|cap| cap[1].to_string())
+                .collect(),
+            next: 0,
+        }
+    }
+
+    fn next(&mut self) -> String {
+        loop {
+            let label = format!("fn-{}", self.next);
+            self.next += 1;
+            if !self.used.contains(&label) {
+                return label;
+            }
+        }
+    }
+}
+
+/// A footnote, either defined with `[^label]: content` or inline with `^[content]`.
+#[derive(Debug, PartialEq)]
+pub struct Footnote {
+    pub label: String,
+    /// The footnote's markdown, possibly spanning several paragraphs.
+    pub content: String,
+}
+
+/// Separates footnote definitions and Obsidian's inline footnotes from the text, since cmark
+/// can't handle the latter. Inline footnotes are replaced by a reference to a generated label.
+pub fn extract_footnotes(markdown: &str) -> (String, Vec<Footnote>) {
+    let mut content = String::with_capacity(markdown.len());
+    let mut footnotes = Vec::new();
+    let mut definition: Option<Footnote> = None;
+    let mut blank_lines = 0;
+    let mut fence: Option<String> = None;
+    let mut paragraph = String::new();
+    let mut labels = InlineLabels::new(markdown);
+
+    for line in markdown.lines() {
+        if let Some(marker) = &fence {
+            content.push_str(line);
+            content.push('\n');
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+            }
+            continue;
+        }
+
+        if let Some(footnote) = &mut definition {
+            if line.trim().is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+            let indented = line.starts_with("    ") || line.starts_with('\t');
+            let lazy_continuation = blank_lines == 0
+                && !DEFINITION.is_match(line)
+                && !line.starts_with('#')
+                && fence_marker(line).is_none();
+            if indented || lazy_continuation {
+                for _ in 0..blank_lines {
+                    footnote.content.push('\n');
+                }
+                footnote.content.push('\n');
+                footnote.content.push_str(dedent(line));
+                blank_lines = 0;
+                continue;
+            }
+            footnotes.push(definition.take().unwrap());
+            for _ in 0..blank_lines {
+                paragraph.push('\n');
+            }
+            blank_lines = 0;
+        }
+
+        if let Some(cap) = DEFINITION.captures(line) {
+            definition = Some(Footnote {
+                label: cap[1].into(),
+                content: cap[2].into(),
+            });
+        } else if let Some(marker) = fence_marker(line) {
+            content.push_str(&separate_inline_footnotes(
+                &paragraph,
+                &mut footnotes,
+                &mut labels,
+            ));
+            paragraph.clear();
+            content.push_str(line);
+            content.push('\n');
+            fence = Some(marker);
+        } else {
+            paragraph.push_str(line);
+            paragraph.push('\n');
+        }
+    }
+
+    if let Some(footnote) = definition {
+        footnotes.push(footnote);
+    }
+    content.push_str(&separate_inline_footnotes(
+        &paragraph,
+        &mut footnotes,
+        &mut labels,
+    ));
+    (content, footnotes)
+}
+
+/// Whether a footnote's content has anything but paragraphs, e.g. a list or code block, which
+/// can't be rendered inline.
+pub fn has_block_content(content: &str) -> bool {
+    Parser::new(content).any(|event| match event {
+        Event::Start(tag) => !matches!(
+            tag,
+            Tag::Paragraph
+                | Tag::Emphasis
+                | Tag::Strong
+                | Tag::Strikethrough
+                | Tag::Link(..)
+                | Tag::Image(..)
+        ),
+        Event::Rule => true,
+        _ => false,
+    })
+}
+
+/// Returns the characters opening a fenced code block, if the line starts one.
+pub fn fence_marker(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    for fence_char in ['`', '~'] {
+        let length = trimmed.chars().take_while(|c| *c == fence_char).count();
+        if length >= 3 {
+            return Some(fence_char.to_string().repeat(length));
+        }
+    }
+    None
+}
+
+/// Removes one level of indentation from a footnote's continuation line.
+fn dedent(line: &str) -> &str {
+    line.strip_prefix('\t')
+        .or_else(|| line.strip_prefix("    "))
+        .unwrap_or(line)
+}
+
+/// Replaces all `^[inline footnotes]` with references, keeping code spans and indented code
+/// blocks intact.
+fn separate_inline_footnotes(
+    text: &str,
+    footnotes: &mut Vec<Footnote>,
+    labels: &mut InlineLabels,
+) -> String {
+    let code_blocks = indented_code_blocks(text);
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let offset = text.len() - rest.len();
+        if let Some(code_block) = code_blocks.iter().find(|block| block.start == offset) {
+            result.push_str(&text[code_block.clone()]);
+            rest = &text[code_block.end..];
+        } else if c == '\\' {
+            let escaped = rest.chars().nth(1).map_or(1, |e| 1 + e.len_utf8());
+            result.push_str(&rest[..escaped]);
+            rest = &rest[escaped..];
+        } else if c == '`' {
+            let span = code_span_length(rest);
+            result.push_str(&rest[..span]);
+            rest = &rest[span..];
+        } else if rest.starts_with("^[") {
+            match closing_bracket(&rest[1..]) {
+                Some(end) => {
+                    let label = labels.next();
+                    result.push_str(&format!("[^{}]", label));
+                    footnotes.push(Footnote {
+                        label,
+                        content: rest[2..end + 1].trim().replace('\n', " "),
+                    });
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    result.push_str("^[");
+                    rest = &rest[2..];
+                }
+            }
+        } else {
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    result
+}
+
+/// Finds the indented code blocks within `text`.
+pub fn indented_code_blocks(text: &str) -> Vec<Range<usize>> {
+    Parser::new(text)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the length of the code span starting at the beginning of `text`, or of the run of
+/// backticks if the span isn't closed.
+fn code_span_length(text: &str) -> usize {
+    let ticks = text.chars().take_while(|c| *c == '`').count();
+    let mut offset = ticks;
+    while let Some(start) = text[offset..].find('`') {
+        let start = offset + start;
+        let length = text[start..].chars().take_while(|c| *c == '`').count();
+        if length == ticks {
+            return start + length;
+        }
+        offset = start + length;
+    }
+    ticks
+}
+
+/// Finds the `]` matching the `[` at the beginning of `text`, skipping nested brackets, escaped
+/// characters and code spans. The search stops at the end of the paragraph, so an unclosed `^[`
+/// can't reach into the next one.
+fn closing_bracket(text: &str) -> Option<usize> {
+    let text = &text[..paragraph_end(text)];
+    let mut depth = 0;
+    let mut offset = 0;
+    while let Some(c) = text[offset..].chars().next() {
+        match c {
+            '\\' => {
+                offset += text[offset + 1..]
+                    .chars()
+                    .next()
+                    .map_or(0, |e| e.len_utf8())
+            }
+            '`' => {
+                offset += code_span_length(&text[offset..]);
+                continue;
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(offset);
+                }
+            }
+            _ => {}
+        }
+        offset += c.len_utf8();
+    }
+    None
+}
+
+/// Returns the offset of the first blank line within `text`, or its length if there is none.
+fn paragraph_end(text: &str) -> usize {
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if offset > 0 && line.trim().is_empty() {
+            return offset;
+        }
+        offset += line.len();
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_footnotes, has_block_content, Footnote};
+
+    fn footnote(label: &str, content: &str) -> Footnote {
+        Footnote {
+            label: label.into(),
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn extract_multiple_inline_footnotes() {
+        let (content, footnotes) = extract_footnotes("One^[first] and two^[second].");
+        assert_eq!(content, "One[^fn-0] and two[^fn-1].\n");
+        assert_eq!(
+            footnotes,
+            [footnote("fn-0", "first"), footnote("fn-1", "second")]
+        );
+    }
+
+    #[test]
+    fn inline_labels_dont_collide() {
+        let (content, footnotes) = extract_footnotes("A[^1] B^[b] C[^fn-1]\n\n[^1]: a\n[^fn-1]: c");
+        assert_eq!(content, "A[^1] B[^fn-0] C[^fn-1]\n\n");
+        assert_eq!(
+            footnotes,
+            [
+                footnote("1", "a"),
+                footnote("fn-1", "c"),
+                footnote("fn-0", "b")
+            ]
+        );
+
+        let (content, _) = extract_footnotes("A^[a] B^[b] C[^fn-1]");
+        assert_eq!(content, "A[^fn-0] B[^fn-2] C[^fn-1]\n");
+    }
+
+    #[test]
+    fn extract_inline_footnotes_with_brackets() {
+        let (content, footnotes) =
+            extract_footnotes("Text^[see [x](y) and `a]`] and \\^[not a footnote]");
+        assert_eq!(content, "Text[^fn-0] and \\^[not a footnote]\n");
+        assert_eq!(footnotes, [footnote("fn-0", "see [x](y) and `a]`")]);
+    }
+
+    #[test]
+    fn unclosed_inline_footnotes_end_with_the_paragraph() {
+        let markdown = "An unclosed ^[footnote\n\nAnother paragraph] with^[a note].";
+        let (content, footnotes) = extract_footnotes(markdown);
+        assert_eq!(
+            content,
+            "An unclosed ^[footnote\n\nAnother paragraph] with[^fn-0].\n"
+        );
+        assert_eq!(footnotes, [footnote("fn-0", "a note")]);
+    }
+
+    #[test]
+    fn ignore_inline_footnotes_in_code() {
+        let markdown =
+            "Use `^[x]` for footnotes.\n\n```\n^[x]\n[^1]: no definition\n```\n\n    ^[indented]\n";
+        let (content, footnotes) = extract_footnotes(markdown);
+        assert_eq!(content, markdown);
+        assert!(footnotes.is_empty());
+    }
+
+    #[test]
+    fn detect_block_content() {
+        assert!(!has_block_content(
+            "Some *text* and [a link](x).\n\nSecond paragraph."
+        ));
+        assert!(has_block_content("A list:\n\n- one\n- two"));
+        assert!(has_block_content("Code:\n\n```\nx\n```"));
+    }
+
+    #[test]
+    fn extract_multi_paragraph_definitions() {
+        let (content, footnotes) = extract_footnotes(
+            "Text[^1] and[^2].\n\n[^1]: First line\ncontinued.\n\n    Second paragraph.\n[^2]: Other\n\nMore text.",
+        );
+        assert_eq!(content, "Text[^1] and[^2].\n\n\nMore text.\n");
+        assert_eq!(
+            footnotes,
+            [
+                footnote("1", "First line\ncontinued.\n\nSecond paragraph."),
+                footnote("2", "Other")
+            ]
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use crate::context::GeneratorContext;
+use crate::hash::content_hash;
+use crate::images;
+use crate::links::resolve_link;
+use crate::markdown::{render_markdown, resolve_url, Asset, LinkTarget};
+use crate::theme;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Locale, NaiveDate};
+use log::debug;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use tera::{from_value, to_value, Error, Result, Value};
+
+/// Where assets referenced from templates are published with `asset-paths: post`, since they don't
+/// belong to any single post.
+const TEMPLATE_ASSET_DIR: &str = "static";
+
+/// Registers campfire's functions and filters with the templates. Since they need to know about all
+/// posts, this happens once all posts are rendered, and they're not available to shortcodes. The
+/// functions refer to the returned context, whose templates they're registered with.
+pub fn register_functions(ctx: GeneratorContext) -> Arc<GeneratorContext> {
+    Arc::new_cyclic(|site: &Weak<GeneratorContext>| {
+        let mut ctx = ctx;
+        let s = site.clone();
+        ctx.tera
+            .register_function("get_url", move |args: &HashMap<String, Value>| {
+                get_url(&*upgrade(&s)?, args)
+            });
+        let s = site.clone();
+        ctx.tera
+            .register_function("get_post", move |args: &HashMap<String, Value>| {
+                get_post(&*upgrade(&s)?, args)
+            });
+        let s = site.clone();
+        ctx.tera
+            .register_function("get_taxonomy", move |args: &HashMap<String, Value>| {
+                get_taxonomy(&*upgrade(&s)?, args)
+            });
+        let s = site.clone();
+        ctx.tera
+            .register_function("resize_image", move |args: &HashMap<String, Value>| {
+                resize_image(&*upgrade(&s)?, args)
+            });
+        let s = site.clone();
+        ctx.tera
+            .register_function("asset_hash", move |args: &HashMap<String, Value>| {
+                asset_hash(&*upgrade(&s)?, args)
+            });
+        let s = site.clone();
+        ctx.tera.register_filter(
+            "markdown",
+            move |value: &Value, _: &HashMap<String, Value>| {
+                let site = upgrade(&s)?;
+                let markdown: String = from_value(value.clone())?;
+                let (html, assets) = render_markdown(&site, &markdown, TEMPLATE_ASSET_DIR);
+                for asset in &assets {
+                    publish(&site, asset)?;
+                }
+                Ok(Value::String(html))
+            },
+        );
+        let locale = ctx.config.locale.clone();
+        ctx.tera.register_filter(
+            "localized_date",
+            move |value: &Value, args: &HashMap<String, Value>| {
+                localized_date(&locale, value, args)
+            },
+        );
+        ctx
+    })
+}
+
+/// The context the functions were registered for, which is around for as long as its templates.
+fn upgrade(site: &Weak<GeneratorContext>) -> Result<Arc<GeneratorContext>> {
+    site.upgrade()
+        .ok_or_else(|| Error::msg("The site is no longer being generated"))
+}
+
+/// `get_url(path="notes/a.md")` returns the URL a post or file within the vault is published at,
+/// or the URL of a file within `.campfire/static` or the theme's static files.
+fn get_url(ctx: &GeneratorContext, args: &HashMap<String, Value>) -> Result<Value> {
+    let path = string_arg("get_url", args, "path")?;
+    if let Some((url, target)) = resolve_url(ctx, &ctx.base_dir, TEMPLATE_ASSET_DIR, &path) {
+        if let LinkTarget::Asset(asset) = target {
+            publish(ctx, &asset)?;
+        }
+        return Ok(Value::String(url));
+    }
+
+    let static_file = path.trim_start_matches('/');
+    if find_static_file(ctx, static_file).is_some() {
+        return Ok(Value::String(format!(
+            "{}/{}",
+            ctx.config.base_url, static_file
+        )));
+    }
+    Err(Error::msg(format!("get_url: could not find {}", path)))
+}
+
+/// `get_post(path="notes/a.md")` returns a published post, as passed to `post.html`.
+fn get_post(ctx: &GeneratorContext, args: &HashMap<String, Value>) -> Result<Value> {
+    let path = string_arg("get_post", args, "path")?;
+    let post = resolve_link(ctx, &ctx.base_dir, &path).and_then(|link| {
+        ctx.posts
+            .iter()
+            .find(|(file, _)| file.path.strip_prefix(&ctx.base_dir).unwrap() == link.path)
+    });
+    match post {
+        Some((_, post)) => to_value(post).map_err(Error::json),
+        None => Err(Error::msg(format!(
+            "get_post: {} is not a published post",
+            path
+        ))),
+    }
+}
+
+/// `get_taxonomy(kind="tags")` returns all terms of a taxonomy with their posts.
+fn get_taxonomy(ctx: &GeneratorContext, args: &HashMap<String, Value>) -> Result<Value> {
+    let kind = string_arg("get_taxonomy", args, "kind")?;
+    ctx.global_context
+        .get("taxonomies")
+        .and_then(|taxonomies| taxonomies.get(&kind))
+        .cloned()
+        .ok_or_else(|| Error::msg(format!("get_taxonomy: there's no taxonomy {}", kind)))
+}
+
+/// `resize_image(path="img/a.png", width=400)` returns the `url`, `width` and `height` of an image
+/// resized to (at most) the given width, or of the original image if it can't be resized.
+fn resize_image(ctx: &GeneratorContext, args: &HashMap<String, Value>) -> Result<Value> {
+    let path = string_arg("resize_image", args, "path")?;
+    let width: usize = match args.get("width") {
+        Some(width) => from_value(width.clone())?,
+        None => return Err(Error::msg("resize_image: missing argument width")),
+    };
+    let link = resolve_link(ctx, &ctx.base_dir, &path)
+        .filter(|link| ctx.base_dir.join(&link.path).is_file())
+        .ok_or_else(|| Error::msg(format!("resize_image: could not find {}", path)))?;
+
+    let mut image = HashMap::new();
+    match images::resize_image(ctx, TEMPLATE_ASSET_DIR, &link.path, width) {
+        Some((variant, height)) => {
+            publish(ctx, &variant.asset)?;
+            image.insert("url", to_value(variant.asset.url(ctx))?);
+            image.insert("width", to_value(variant.width)?);
+            image.insert("height", to_value(height)?);
+        }
+        None => {
+            let asset = Asset::new(ctx, TEMPLATE_ASSET_DIR, link.path)?;
+            publish(ctx, &asset)?;
+            let content = fs::read(ctx.base_dir.join(&asset.source))?;
+            let size = imagesize::blob_size(&content).ok();
+            image.insert("url", to_value(asset.url(ctx))?);
+            image.insert("width", to_value(size.as_ref().map(|s| s.width))?);
+            image.insert("height", to_value(size.as_ref().map(|s| s.height))?);
+        }
+    }
+    to_value(image).map_err(Error::json)
+}
+
+/// `asset_hash(path="style.css")` returns a short hash of a static file (see `get_url`) or a file
+/// within the vault, e.g. to append to its URL so that browsers fetch it again once it changes.
+fn asset_hash(ctx: &GeneratorContext, args: &HashMap<String, Value>) -> Result<Value> {
+    let path = string_arg("asset_hash", args, "path")?;
+    let path = path.trim_start_matches('/');
+    let file = find_static_file(ctx, path)
+        .or_else(|| Some(ctx.base_dir.join(path)).filter(|file| file.is_file()))
+        .ok_or_else(|| Error::msg(format!("asset_hash: could not find {}", path)))?;
+    let hash = content_hash(&fs::read(file)?);
+    match hash.get(..12) {
+        Some(hash) => Ok(Value::String(hash.into())),
+        None => Err(Error::msg(format!("asset_hash: could not hash {}", path))),
+    }
+}
+
+/// `localized_date(format="%e. %B %Y", locale="de")` formats a date like Tera's `date`, with month
+/// and weekday names in the given language, or `locale` from the configuration.
+fn localized_date(
+    default_locale: &str,
+    value: &Value,
+    args: &HashMap<String, Value>,
+) -> Result<Value> {
+    let format = match args.get("format") {
+        Some(format) => from_value(format.clone())?,
+        None => "%Y-%m-%d".to_string(),
+    };
+    let locale: String = match args.get("locale") {
+        Some(locale) => from_value(locale.clone())?,
+        None => default_locale.into(),
+    };
+    let date: String = from_value(value.clone())?;
+    let date = DateTime::parse_from_rfc3339(&date)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().fixed_offset())
+        })
+        .map_err(|_| Error::msg(format!("localized_date: could not parse {}", date)))?;
+
+    let locale = parse_locale(&locale)
+        .ok_or_else(|| Error::msg(format!("localized_date: unknown locale {}", locale)))?;
+    format_localized(&date, &format, locale).map(Value::String)
+}
+
+/// Languages whose main region isn't the language's code in upper case, e.g. not `en_EN`.
+const DEFAULT_REGIONS: [(&str, &str); 10] = [
+    ("en", "US"),
+    ("ja", "JP"),
+    ("zh", "CN"),
+    ("ko", "KR"),
+    ("sv", "SE"),
+    ("da", "DK"),
+    ("cs", "CZ"),
+    ("el", "GR"),
+    ("uk", "UA"),
+    ("nb", "NO"),
+];
+
+/// A locale such as `de_AT` or `de-AT`, or just a language such as `de` for its main region.
+fn parse_locale(locale: &str) -> Option<Locale> {
+    let locale = locale.replace('-', "_");
+    if let Ok(locale) = locale.parse::<Locale>() {
+        return Some(locale);
+    }
+    let region = DEFAULT_REGIONS
+        .iter()
+        .find(|(language, _)| *language == locale)
+        .map(|(_, region)| region.to_string())
+        .unwrap_or_else(|| locale.to_uppercase());
+    format!("{}_{}", locale, region).parse().ok()
+}
+
+/// Formats a date with chrono's `strftime` syntax, using the locale's month and weekday names.
+fn format_localized(date: &DateTime<FixedOffset>, format: &str, locale: Locale) -> Result<String> {
+    let items: Vec<Item> = StrftimeItems::new_with_locale(format, locale).collect();
+    if items.contains(&Item::Error) {
+        return Err(Error::msg(format!(
+            "localized_date: invalid format {}",
+            format
+        )));
+    }
+    let mut formatted = String::new();
+    write!(
+        formatted,
+        "{}",
+        date.format_localized_with_items(items.iter(), locale)
+    )
+    .map_err(|_| Error::msg(format!("localized_date: could not format {}", date)))?;
+    Ok(formatted)
+}
+
+/// Copies an asset referenced from a template, unless it's already been published.
+fn publish(ctx: &GeneratorContext, asset: &Asset) -> Result<()> {
+    let target = ctx.output_dir.join(&asset.target);
+    if !target.exists() {
+        debug!("  Copying asset {}", target.to_str().unwrap());
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(ctx.base_dir.join(&asset.source), target)?;
+    }
+    Ok(())
+}
+
+/// Finds a file within `.campfire/static`, or the theme's static files.
+fn find_static_file(ctx: &GeneratorContext, path: &str) -> Option<PathBuf> {
+    let mut static_dirs = vec![ctx.base_dir.join(".campfire").join("static")];
+    if let Some(theme_dir) = theme::theme_dir(&ctx.base_dir, &ctx.config) {
+        static_dirs.push(theme_dir.join("static"));
+    }
+    static_dirs
+        .into_iter()
+        .map(|dir| dir.join(path))
+        .find(|file| file.is_file())
+}
+
+fn string_arg(function: &str, args: &HashMap<String, Value>, name: &str) -> Result<String> {
+    match args.get(name) {
+        Some(value) => from_value(value.clone())
+            .map_err(|_| Error::msg(format!("{}: argument {} must be a string", function, name))),
+        None => Err(Error::msg(format!(
+            "{}: missing argument {}",
+            function, name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_localized, parse_locale};
+    use chrono::DateTime;
+
+    #[test]
+    fn format_localized_dates() {
+        let date = DateTime::parse_from_rfc3339("2022-03-07T09:05:00+01:00").unwrap();
+        let de = parse_locale("de-AT").unwrap();
+        assert_eq!(
+            format_localized(&date, "%A, %e. %B %Y", de).unwrap(),
+            "Montag,  7. März 2022"
+        );
+        let fr = parse_locale("fr").unwrap();
+        assert_eq!(
+            format_localized(&date, "%-d %B %H:%M%:z", fr).unwrap(),
+            "7 mars 09:05+01:00"
+        );
+        assert!(parse_locale("en").is_some());
+        assert!(parse_locale("xx").is_none());
+        assert!(format_localized(&date, "%Q", de).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use sha2::{Digest, Sha256};
+
+/// Returns the hex-encoded SHA-256 hash of a file's content.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Returns the hex-encoded SHA-256 hash of several parts, e.g. a file's content and the command
+/// it's processed with. Each part is prefixed with its length, so that moving bytes from one part
+/// to the next changes the hash.
+pub fn hash_parts(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_parts;
+
+    #[test]
+    fn parts_are_separated() {
+        assert_ne!(hash_parts(&[b"ab", b"c"]), hash_parts(&[b"a", b"bc"]));
+    }
+}
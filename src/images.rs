@@ -0,0 +1,368 @@
+use crate::context::GeneratorContext;
+use crate::hash::hash_parts;
+use crate::markdown::Asset;
+use log::{debug, warn};
+use pulldown_cmark::escape::escape_html;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Image formats which can be resized, everything else is copied as it is.
+const RESIZABLE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+/// An image resized into several widths and formats, for use with `srcset`.
+pub struct ResponsiveImage {
+    pub width: usize,
+    pub height: usize,
+    /// All generated files, by format and ascending width.
+    pub variants: Vec<ImageVariant>,
+}
+
+pub struct ImageVariant {
+    pub asset: Asset,
+    pub width: usize,
+    pub format: String,
+}
+
+/// Resizes an image into the configured widths and formats, or returns `None` if image processing
+/// is disabled or fails, e.g. since the configured command isn't installed.
+pub fn process_image(ctx: &GeneratorContext, slug: &str, source: &Path) -> Option<ResponsiveImage> {
+    let config = &ctx.config.images;
+    let extension = source.extension()?.to_str()?.to_lowercase();
+    if config.widths.is_empty() || !RESIZABLE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    let source_path = ctx.base_dir.join(source);
+    let content = match fs::read(&source_path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Could not read image {}: {}", source.to_str().unwrap(), e);
+            return None;
+        }
+    };
+    let (original_width, original_height) = oriented_size(&content)?;
+
+    let mut widths: Vec<usize> = config
+        .widths
+        .iter()
+        .map(|w| *w as usize)
+        .filter(|w| *w < original_width)
+        .collect();
+    if widths.len() < config.widths.len() {
+        widths.push(original_width);
+    }
+    widths.sort_unstable();
+    widths.dedup();
+
+    let mut formats = vec![normalize_format(&extension)];
+    for format in config.formats.iter().map(|f| normalize_format(f)) {
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+
+    let hash = image_hash(ctx, &content);
+    let stem = source.file_stem()?.to_str()?;
+    let mut variants = Vec::new();
+    for format in &formats {
+        for width in &widths {
+            variants.push(variant(
+                ctx,
+                slug,
+                &source_path,
+                stem,
+                &hash,
+                *width,
+                format,
+            )?);
+        }
+    }
+
+    Some(ResponsiveImage {
+        width: original_width,
+        height: original_height,
+        variants,
+    })
+}
+
+/// Resizes an image to a single width, keeping its format, e.g. for thumbnails. Returns the
+/// resized image and its height, or `None` if the image can't be resized.
+pub fn resize_image(
+    ctx: &GeneratorContext,
+    slug: &str,
+    source: &Path,
+    width: usize,
+) -> Option<(ImageVariant, usize)> {
+    let extension = source.extension()?.to_str()?.to_lowercase();
+    if !RESIZABLE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    let source_path = ctx.base_dir.join(source);
+    let content = fs::read(&source_path).ok()?;
+    let (original_width, original_height) = oriented_size(&content)?;
+    let width = width.min(original_width);
+    let height = original_height * width / original_width.max(1);
+
+    let hash = image_hash(ctx, &content);
+    let stem = source.file_stem()?.to_str()?;
+    let format = normalize_format(&extension);
+    let variant = variant(ctx, slug, &source_path, stem, &hash, width, &format)?;
+    Some((variant, height))
+}
+
+/// Returns an image's width and height as displayed, which are swapped if its EXIF orientation
+/// rotates it by 90 degrees, e.g. for portrait photos taken with a phone. Resized images are
+/// rotated accordingly with `-auto-orient`.
+fn oriented_size(content: &[u8]) -> Option<(usize, usize)> {
+    let size = imagesize::blob_size(content).ok()?;
+    match exif_orientation(content) {
+        Some(5..=8) => Some((size.height, size.width)),
+        _ => Some((size.width, size.height)),
+    }
+}
+
+/// Reads the EXIF orientation of a JPEG, PNG or WebP image, if it has one.
+fn exif_orientation(content: &[u8]) -> Option<u16> {
+    if content.starts_with(&[0xff, 0xd8]) {
+        // JPEG segments, with the EXIF data in the APP1 segment
+        let mut offset = 2;
+        while let [0xff, marker, high, low, ..] = *content.get(offset..)? {
+            let length = u16::from_be_bytes([high, low]) as usize;
+            let data = content.get(offset + 4..offset + 2 + length)?;
+            if marker == 0xe1 && data.starts_with(b"Exif\0\0") {
+                return tiff_orientation(&data[6..]);
+            }
+            if marker == 0xda {
+                // the image data starts
+                return None;
+            }
+            offset += 2 + length;
+        }
+        None
+    } else if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let mut offset = 8;
+        while let Some(header) = content.get(offset..offset + 8) {
+            let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let data = content.get(offset + 8..offset + 8 + length)?;
+            if &header[4..] == b"eXIf" {
+                return tiff_orientation(data);
+            }
+            offset += 12 + length;
+        }
+        None
+    } else if content.starts_with(b"RIFF") && content.get(8..12) == Some(b"WEBP") {
+        let mut offset = 12;
+        while let Some(header) = content.get(offset..offset + 8) {
+            let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let data = content.get(offset + 8..offset + 8 + length)?;
+            if &header[..4] == b"EXIF" {
+                return tiff_orientation(data.strip_prefix(b"Exif\0\0").unwrap_or(data));
+            }
+            offset += 8 + length + length % 2;
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// Reads the orientation tag from the first IFD of EXIF data, which is laid out like a TIFF file.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| u16_at(*entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// The lowercase file extension for an image format, so that `jpeg` and `JPG` are the same format.
+fn normalize_format(format: &str) -> String {
+    match format.to_lowercase().as_str() {
+        "jpeg" => "jpg".into(),
+        format => format.into(),
+    }
+}
+
+/// Identifies an image and how it's processed, so that changing either invalidates cached images.
+fn image_hash(ctx: &GeneratorContext, content: &[u8]) -> String {
+    let config = &ctx.config.images;
+    hash_parts(&[
+        content,
+        config.command.as_bytes(),
+        &[config.strip_exif as u8],
+    ])
+}
+
+/// Returns the image resized to the given width and format, resizing it unless it's cached.
+fn variant(
+    ctx: &GeneratorContext,
+    slug: &str,
+    source_path: &Path,
+    stem: &str,
+    hash: &str,
+    width: usize,
+    format: &str,
+) -> Option<ImageVariant> {
+    let cached = ctx
+        .cache_dir
+        .join("images")
+        .join(format!("{}-{}.{}", hash, width, format));
+    if !cached.exists() && !resize(ctx, source_path, &cached, width) {
+        return None;
+    }
+    // the cache may be configured outside of the vault
+    let source = cached
+        .strip_prefix(&ctx.base_dir)
+        .unwrap_or(&cached)
+        .to_path_buf();
+    let file_name = format!("{}-{}.{}", stem, width, format);
+    Some(ImageVariant {
+        asset: Asset::generated(ctx, slug, source, &file_name, hash),
+        width,
+        format: format.into(),
+    })
+}
+
+/// Runs the configured command to resize the image, which also converts it to the output's format.
+fn resize(ctx: &GeneratorContext, source: &Path, target: &Path, width: usize) -> bool {
+    let config = &ctx.config.images;
+    if let Err(e) = fs::create_dir_all(target.parent().unwrap()) {
+        warn!("Could not create the image cache: {}", e);
+        return false;
+    }
+    debug!("  Resizing {} to {}px", source.to_str().unwrap(), width);
+
+    let mut command = Command::new(&config.command);
+    command.arg(source).arg("-auto-orient");
+    if config.strip_exif {
+        command.arg("-strip");
+    }
+    let status = command
+        .arg("-resize")
+        .arg(format!("{}x", width))
+        .arg(target)
+        .stdout(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!("Could not resize {}: {}", source.to_str().unwrap(), status);
+            false
+        }
+        Err(e) => {
+            warn!("Could not run image command '{}': {}", config.command, e);
+            false
+        }
+    }
+}
+
+impl ResponsiveImage {
+    /// Writes an `<img>` with `srcset`, within a `<picture>` if there's additional formats.
+    pub fn to_html(&self, ctx: &GeneratorContext, alt: &str, title: &str) -> String {
+        let sizes = &ctx.config.images.sizes;
+        let original_format = &self.variants[0].format;
+        let srcset = |format: &str| {
+            self.variants
+                .iter()
+                .filter(|v| v.format == format)
+                .map(|v| format!("{} {}w", v.asset.url(ctx), v.width))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let largest = self
+            .variants
+            .iter()
+            .rfind(|v| &v.format == original_format)
+            .unwrap();
+
+        let mut html = String::new();
+        let mut alt_escaped = String::new();
+        escape_html(&mut alt_escaped, alt).unwrap();
+        let img = format!(
+            "<img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\"",
+            largest.asset.url(ctx),
+            srcset(original_format),
+            sizes,
+            self.width,
+            self.height,
+            alt_escaped
+        );
+        let mut formats: Vec<&String> = self.variants.iter().map(|v| &v.format).collect();
+        formats.dedup();
+        if formats.len() > 1 {
+            html.push_str("<picture>");
+            for format in formats.iter().skip(1) {
+                html.push_str(&format!(
+                    "<source type=\"image/{}\" srcset=\"{}\" sizes=\"{}\" />",
+                    format.replace("jpg", "jpeg"),
+                    srcset(format),
+                    sizes
+                ));
+            }
+        }
+        html.push_str(&img);
+        if !title.is_empty() {
+            let mut title_escaped = String::new();
+            escape_html(&mut title_escaped, title).unwrap();
+            html.push_str(&format!(" title=\"{}\"", title_escaped));
+        }
+        html.push_str(" />");
+        if formats.len() > 1 {
+            html.push_str("</picture>");
+        }
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exif_orientation, normalize_format};
+
+    #[test]
+    fn read_exif_orientation() {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1, 0x00, 0x22];
+        jpeg.extend_from_slice(b"Exif\0\0MM\0*\0\0\0\x08\0\x01");
+        jpeg.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0, 0, 0, 1, 0x00, 0x06, 0, 0]);
+        jpeg.extend_from_slice(&[0xff, 0xda, 0x00, 0x02]);
+        assert_eq!(exif_orientation(&jpeg), Some(6));
+
+        assert_eq!(
+            exif_orientation(&[0xff, 0xd8, 0xff, 0xda, 0x00, 0x02]),
+            None
+        );
+        assert_eq!(exif_orientation(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn normalize_formats() {
+        assert_eq!(normalize_format("JPEG"), "jpg");
+        assert_eq!(normalize_format("jpg"), "jpg");
+        assert_eq!(normalize_format("WebP"), "webp");
+    }
+}
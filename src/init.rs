@@ -0,0 +1,179 @@
+use crate::dir;
+use crate::markdown::parse_frontmatter;
+use log::info;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use tera::{Context, Tera};
+
+/// Tags commonly used to mark notes for publishing, preferred over any other tag.
+const PUBLISH_TAGS: [&str; 4] = ["published", "publish", "public", "blog"];
+/// The folder with the generated site, relative to the vault.
+const OUTPUT_DIR: &str = ".campfire/out";
+
+/// Creates a commented `campfire.yaml`, the templates and static folders, and ignores the
+/// generated site in `.gitignore`. Unless `interactive`, the suggested values are used as they are.
+pub fn init(
+    base_dir: &Path,
+    config_file: &Path,
+    require_tag: Option<&str>,
+    interactive: bool,
+) -> Result<(), Box<dyn Error>> {
+    if config_file.exists() {
+        return Err(format!("{} already exists", config_file.to_str().unwrap()).into());
+    }
+
+    let tags = count_tags(base_dir);
+    let name = base_dir
+        .canonicalize()?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(slug::slugify)
+        .unwrap_or_else(|| "my-site".into());
+
+    let mut answers = Answers {
+        name: name.clone(),
+        title: name,
+        author: String::new(),
+        require_tag: require_tag
+            .map(String::from)
+            .unwrap_or_else(|| suggest_require_tag(&tags)),
+        base_url: "http://localhost".into(),
+    };
+    if interactive {
+        answers.ask(&tags)?;
+    }
+
+    let mut context = Context::new();
+    context.insert("name", &answers.name);
+    context.insert("title", &answers.title);
+    context.insert("author", &answers.author);
+    context.insert("require_tag", &answers.require_tag);
+    context.insert("base_url", &answers.base_url);
+    let config = Tera::one_off(include_str!("templates/campfire.yaml"), &context, false)?;
+
+    // templates and static files are always within `.campfire`, even with a custom `--config`
+    let campfire_dir = base_dir.join(".campfire");
+    fs::create_dir_all(campfire_dir.join("templates"))?;
+    fs::create_dir_all(campfire_dir.join("static"))?;
+    if let Some(config_dir) = config_file.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
+    fs::write(config_file, config)?;
+    info!("Created {}", config_file.to_str().unwrap());
+
+    ignore_output(base_dir)?;
+    Ok(())
+}
+
+struct Answers {
+    name: String,
+    title: String,
+    author: String,
+    require_tag: String,
+    base_url: String,
+}
+
+impl Answers {
+    fn ask(&mut self, tags: &HashMap<String, usize>) -> io::Result<()> {
+        self.name = prompt("Name", &self.name)?;
+        self.title = prompt("Title", &self.name)?;
+        self.author = prompt("Author", &self.author)?;
+
+        let mut tags: Vec<(&String, &usize)> = tags.iter().collect();
+        tags.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        if !tags.is_empty() {
+            let tags: Vec<String> = tags
+                .iter()
+                .take(10)
+                .map(|(tag, count)| format!("{} ({})", tag, count))
+                .collect();
+            println!("Tags in your notes: {}", tags.join(", "));
+        }
+        self.require_tag = prompt("Only publish notes tagged", &self.require_tag)?;
+        self.base_url = prompt("Base URL", &self.base_url)?;
+        Ok(())
+    }
+}
+
+/// Asks for a value on stdin, returning `default` if nothing is entered.
+fn prompt(question: &str, default: &str) -> io::Result<String> {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.into())
+}
+
+/// Counts how many notes have each tag in their frontmatter, skipping notes whose frontmatter
+/// can't be parsed.
+fn count_tags(base_dir: &Path) -> HashMap<String, usize> {
+    let mut tags = HashMap::new();
+    for path in dir::find_all_note_paths(base_dir) {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        if let Some(Ok(frontmatter)) = parse_frontmatter(&content) {
+            for tag in frontmatter.tags {
+                *tags.entry(tag).or_default() += 1;
+            }
+        }
+    }
+    tags
+}
+
+/// A tag commonly used for publishing if any note has one, otherwise the most common tag.
+fn suggest_require_tag(tags: &HashMap<String, usize>) -> String {
+    if let Some(tag) = PUBLISH_TAGS.iter().find(|&&tag| tags.contains_key(tag)) {
+        return tag.to_string();
+    }
+    tags.iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+        .map(|(tag, _)| tag.clone())
+        .unwrap_or_else(|| PUBLISH_TAGS[0].into())
+}
+
+/// Adds the generated site to the vault's `.gitignore`, unless it's already there.
+fn ignore_output(base_dir: &Path) -> io::Result<()> {
+    let gitignore = base_dir.join(".gitignore");
+    let mut content = fs::read_to_string(&gitignore).unwrap_or_default();
+    let ignored = content
+        .lines()
+        .any(|line| line.trim().trim_start_matches('/').trim_end_matches('/') == OUTPUT_DIR);
+    if ignored {
+        return Ok(());
+    }
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(OUTPUT_DIR);
+    content.push('\n');
+    fs::write(&gitignore, content)?;
+    info!("Added {} to {}", OUTPUT_DIR, gitignore.to_str().unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suggest_require_tag;
+    use std::collections::HashMap;
+
+    #[test]
+    fn suggest_tag() {
+        let mut tags = HashMap::new();
+        assert_eq!(suggest_require_tag(&tags), "published");
+
+        tags.insert("rust".to_string(), 3);
+        tags.insert("web".to_string(), 3);
+        tags.insert("draft".to_string(), 1);
+        assert_eq!(suggest_require_tag(&tags), "rust");
+
+        tags.insert("blog".to_string(), 1);
+        assert_eq!(suggest_require_tag(&tags), "blog");
+    }
+}
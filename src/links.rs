@@ -0,0 +1,126 @@
+use crate::config::LinkPaths;
+use crate::context::GeneratorContext;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+
+lazy_static! {
+    static ref URL_SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+}
+
+/// A link to a file within the vault.
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    /// The file's path, relative to the vault.
+    pub path: PathBuf,
+    /// The link's `?query` and `#fragment`, if any.
+    pub suffix: String,
+}
+
+/// Whether a link points to a file within the vault, rather than to another site or an anchor.
+pub fn is_relative_url(dest: &str) -> bool {
+    !dest.is_empty() && !dest.starts_with('#') && !URL_SCHEME.is_match(dest)
+}
+
+/// Resolves a link within a note to a file in the vault. Links starting with `./` or `../` are
+/// relative to the note, links starting with `/` relative to the vault. Anything else is resolved
+/// according to `link-paths`, which by default prefers an existing file next to the note over one
+/// relative to the vault.
+pub fn resolve_link(ctx: &GeneratorContext, note: &Path, dest: &str) -> Option<Link> {
+    if !is_relative_url(dest) {
+        return None;
+    }
+
+    let (path, suffix) = split_suffix(dest);
+    let path = percent_decode_str(path).decode_utf8_lossy().to_string();
+    let note_dir = note
+        .strip_prefix(&ctx.base_dir)
+        .unwrap_or(note)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let candidates = if let Some(path) = path.strip_prefix('/') {
+        vec![normalize(Path::new(""), path)]
+    } else if path.starts_with("./") || path.starts_with("../") {
+        vec![normalize(note_dir, &path)]
+    } else {
+        match ctx.config.link_paths {
+            LinkPaths::Auto => vec![normalize(note_dir, &path), normalize(Path::new(""), &path)],
+            LinkPaths::File => vec![normalize(note_dir, &path)],
+            LinkPaths::Vault => vec![normalize(Path::new(""), &path)],
+        }
+    };
+    let candidates: Vec<PathBuf> = candidates.into_iter().flatten().collect();
+    let path = candidates
+        .iter()
+        .find(|candidate| ctx.base_dir.join(candidate).is_file())
+        .or_else(|| candidates.first())?
+        .clone();
+
+    Some(Link {
+        path,
+        suffix: suffix.into(),
+    })
+}
+
+/// Splits a link into its path and the `?query` or `#fragment` following it.
+fn split_suffix(dest: &str) -> (&str, &str) {
+    match dest.find(['?', '#']) {
+        Some(index) => dest.split_at(index),
+        None => (dest, ""),
+    }
+}
+
+/// Joins a path to a directory, resolving `.` and `..` without leaving the vault.
+fn normalize(dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in dir.join(path).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_relative_url, normalize, split_suffix};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn detect_relative_urls() {
+        assert!(is_relative_url("notes/Other%20Note.md"));
+        assert!(is_relative_url("../img/a.png"));
+        assert!(!is_relative_url("https://example.com"));
+        assert!(!is_relative_url("mailto:someone@example.com"));
+        assert!(!is_relative_url("#heading"));
+    }
+
+    #[test]
+    fn split_query_and_fragment() {
+        assert_eq!(split_suffix("a.md#heading"), ("a.md", "#heading"));
+        assert_eq!(split_suffix("a.pdf?page=2#x"), ("a.pdf", "?page=2#x"));
+        assert_eq!(split_suffix("a.png"), ("a.png", ""));
+    }
+
+    #[test]
+    fn normalize_relative_paths() {
+        let dir = Path::new("projects/x");
+        assert_eq!(
+            normalize(dir, "./y.md"),
+            Some(PathBuf::from("projects/x/y.md"))
+        );
+        assert_eq!(
+            normalize(dir, "../../img/a.png"),
+            Some(PathBuf::from("img/a.png"))
+        );
+        assert_eq!(normalize(dir, "../../../outside.md"), None);
+    }
+}
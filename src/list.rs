@@ -0,0 +1,194 @@
+use crate::config::Config;
+use crate::dir::{self, Exclusion};
+use crate::markdown::{parse_frontmatter, read_markdown_file};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A markdown file within the vault, and whether it's published.
+#[derive(Debug, Serialize)]
+pub struct Note {
+    pub file: String,
+    pub published: bool,
+    pub reason: Option<Exclusion>,
+    pub title: Option<String>,
+    pub date: Option<String>,
+    pub slug: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Note {
+    fn status(&self) -> String {
+        match self.reason {
+            Some(reason) => reason.to_string(),
+            None => "published".into(),
+        }
+    }
+}
+
+/// Lists all markdown files within the vault, sorted by their path.
+pub fn list(base_dir: &Path, config: &Config) -> Vec<Note> {
+    let notes: HashSet<PathBuf> = dir::find_all_note_paths(base_dir).into_iter().collect();
+    let mut paths = dir::find_all_markdown_paths(base_dir);
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let reason = if notes.contains(&path) {
+                fs::read_to_string(&path)
+                    .ok()
+                    .map_or(
+                        Some(Exclusion::Unparsable),
+                        |content| match parse_frontmatter(&content) {
+                            Some(Ok(frontmatter)) => dir::exclusion(&frontmatter, config),
+                            Some(Err(_)) => Some(Exclusion::Unparsable),
+                            None => Some(Exclusion::NoFrontmatter),
+                        },
+                    )
+            } else {
+                Some(Exclusion::SkippedFolder)
+            };
+            let mut note = Note {
+                file: path
+                    .strip_prefix(base_dir)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .into(),
+                published: reason.is_none(),
+                reason,
+                title: None,
+                date: None,
+                slug: None,
+                url: None,
+            };
+            if note.published {
+                let file = read_markdown_file(path).unwrap();
+                let date = file.frontmatter.date;
+                let slug = file.slug(base_dir);
+                note.title = Some(file.title());
+                note.date = date.map(|date| date.format("%Y-%m-%d").to_string());
+                note.url = Some(format!("{}/{}/", config.base_url, slug));
+                note.slug = Some(slug);
+            }
+            note
+        })
+        .collect()
+}
+
+/// The notes as an aligned table.
+pub fn to_table(notes: &[Note]) -> String {
+    let rows: Vec<Vec<String>> = notes
+        .iter()
+        .map(|note| {
+            vec![
+                note.status(),
+                note.file.clone(),
+                note.title.clone().unwrap_or_default(),
+                note.date.clone().unwrap_or_default(),
+                note.slug.clone().unwrap_or_default(),
+                note.url.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let header: Vec<String> = vec!["STATUS", "FILE", "TITLE", "DATE", "SLUG", "URL"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    let mut widths = vec![0; header.len()];
+    for row in Some(&header).into_iter().chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in Some(&header).into_iter().chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// The notes as CSV, with a header row.
+pub fn to_csv(notes: &[Note]) -> String {
+    let mut csv = String::from("file,published,reason,title,date,slug,url\n");
+    for note in notes {
+        let reason = note.reason.map(|reason| reason.to_string());
+        let fields = vec![
+            Some(note.file.clone()),
+            Some(note.published.to_string()),
+            reason,
+            note.title.clone(),
+            note.date.clone(),
+            note.slug.clone(),
+            note.url.clone(),
+        ];
+        let fields: Vec<String> = fields
+            .into_iter()
+            .map(|field| csv_field(&field.unwrap_or_default()))
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_csv, to_table, Note};
+    use crate::dir::Exclusion;
+
+    #[test]
+    fn format_notes() {
+        let notes = vec![
+            Note {
+                file: "a.md".into(),
+                published: true,
+                reason: None,
+                title: Some("Hello, \"World\"".into()),
+                date: Some("2022-08-01".into()),
+                slug: Some("hello-world".into()),
+                url: Some("http://localhost/hello-world/".into()),
+            },
+            Note {
+                file: "_drafts/b.md".into(),
+                published: false,
+                reason: Some(Exclusion::SkippedFolder),
+                title: None,
+                date: None,
+                slug: None,
+                url: None,
+            },
+        ];
+
+        assert_eq!(
+            to_csv(&notes),
+            "file,published,reason,title,date,slug,url\n\
+             a.md,true,,\"Hello, \"\"World\"\"\",2022-08-01,hello-world,http://localhost/hello-world/\n\
+             _drafts/b.md,false,skipped folder,,,,\n"
+        );
+        assert_eq!(
+            to_table(&notes),
+            "STATUS          FILE          TITLE           DATE        SLUG         URL\n\
+             published       a.md          Hello, \"World\"  2022-08-01  hello-world  http://localhost/hello-world/\n\
+             skipped folder  _drafts/b.md\n"
+        );
+    }
+}
@@ -3,20 +3,39 @@ mod commandline;
 mod config;
 mod context;
 mod deserialize;
+mod diagram;
 mod dir;
+mod feed;
+mod footnote;
+mod functions;
+mod hash;
+mod images;
+mod init;
+mod links;
+mod list;
 mod markdown;
+mod navigation;
+mod new;
+mod related;
+mod search;
+mod shortcode;
+mod sitemap;
+mod text;
+mod theme;
+mod transclude;
 
 #[macro_use]
 extern crate lazy_static;
 use crate::build::build;
-use crate::config::read_config;
-use log::info;
+use crate::config::{read_config, Override};
+use crate::new::NewNote;
+use log::{error, info};
 use std::path::PathBuf;
 use std::time::Instant;
 
 fn main() {
     let start = Instant::now();
-    if let Err(_) = std::env::var("LOG") {
+    if std::env::var("LOG").is_err() {
         std::env::set_var("LOG", "info");
     }
     pretty_env_logger::init_custom_env("LOG");
@@ -33,8 +52,28 @@ fn main() {
 
     let config_path = matches.value_of("config").unwrap();
     let config_file = base_dir.join(config_path);
-    let config = read_config(&config_file)
-        .unwrap_or_else(|_| panic!("Could not read config: {}", config_file.to_str().unwrap()));
+    if let Some(("init", init_matches)) = matches.subcommand() {
+        let require_tag = init_matches.value_of("require-tag");
+        let interactive = !init_matches.is_present("yes");
+        if let Err(e) = init::init(&base_dir, &config_file, require_tag, interactive) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut overrides = config::env_overrides();
+    if let Some(base_url) = matches.value_of("base-url") {
+        overrides.push(Override {
+            key: "base-url".into(),
+            value: base_url.into(),
+            source: "--base-url".into(),
+        });
+    }
+    let config = read_config(&config_file, &overrides).unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
     info!(
         "Generating site {} using config {}",
         base_dir.to_str().unwrap(),
@@ -42,7 +81,31 @@ fn main() {
     );
 
     match matches.subcommand() {
-        Some(("build", _)) => build(base_dir.into(), config.into()),
+        Some(("build", _)) => build(base_dir, config),
+        Some(("list", list_matches)) => {
+            let notes = list::list(&base_dir, &config);
+            match list_matches.value_of("format").unwrap() {
+                "json" => println!("{}", serde_json::to_string_pretty(&notes).unwrap()),
+                "csv" => print!("{}", list::to_csv(&notes)),
+                _ => print!("{}", list::to_table(&notes)),
+            }
+        }
+        Some(("new", new_matches)) => {
+            let note = NewNote {
+                title: new_matches.value_of("title").unwrap(),
+                dir: new_matches.value_of("dir"),
+                tags: new_matches
+                    .values_of("tag")
+                    .map(|tags| tags.map(String::from).collect())
+                    .unwrap_or_default(),
+                publish: new_matches.is_present("publish"),
+                archetype: new_matches.value_of("archetype"),
+            };
+            if let Err(e) = new::new_note(&base_dir, &config, note) {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
         _ => panic!(),
     }
     info!("Done in {:?}", start.elapsed());
@@ -1,54 +1,111 @@
-use crate::context::GeneratorContext;
+use crate::config::{AssetPaths, FootnoteStyle};
+use crate::context::{GeneratorContext, PostStatistics};
 use crate::deserialize::{deserialize_tags, utc_date};
-use chrono::{Date, Utc};
+use crate::dir;
+use crate::footnote::{extract_footnotes, has_block_content, Footnote};
+use crate::hash::content_hash;
+use crate::images::process_image;
+use crate::links::{is_relative_url, resolve_link};
+use crate::shortcode::render_shortcodes;
+use crate::text::{plain_text, word_count};
+use crate::transclude::transclude;
+use chrono::NaiveDate;
 use log::info;
 use log::warn;
+use pulldown_cmark::escape::escape_html;
 use pulldown_cmark::{html, CowStr, Event, HeadingLevel, LinkType, Options, Parser, Tag};
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt::Debug;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 lazy_static! {
     // https://github.com/getzola/zola/blob/1ef8c85f53b4988fdafc0e6271cce590515d55aa/components/front_matter/src/lib.rs#L17
     static ref YAML_RE: Regex =
         Regex::new(r"^[[:space:]]*---(\r?\n(?s).*?(?-s))---\r?\n?((?s).*(?-s))$").unwrap();
-
-    static ref INLINE_FOOTNOTE: Regex = Regex::new("\\^\\[(.*)\\]").unwrap();
-    static ref NORMAL_FOOTNOTE: Regex = Regex::new("\\[\\^(.*)\\]:(.*)$").unwrap();
+    /// The marker after which a post's content is no longer part of its summary, e.g. `<!--more-->`.
+    static ref MORE_MARKER: Regex = Regex::new(r"(?i)^<!--\s*more\s*-->\s*$").unwrap();
 }
 
+/// Reads a note with frontmatter, skipping it with a warning if its frontmatter can't be parsed.
 pub fn read_markdown_file(path: PathBuf) -> Option<MarkdownFile> {
-    let content = fs::read_to_string(&path);
-    match content {
-        Ok(content) => {
-            let cap = YAML_RE.captures(&content)?;
-
-            let frontmatter: &str = cap.get(1).map_or("", |m| m.as_str());
-            let markdown = cap.get(2).map_or("", |m| m.as_str());
-            return Some(MarkdownFile {
-                path: path.into(),
-                frontmatter: serde_yaml::from_str(frontmatter.into()).unwrap(),
-                markdown: markdown.into(),
-            });
-        }
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
         Err(_) => {
             info!("Skipping {} (not a text file)", path.to_str().unwrap());
-            None
+            return None;
+        }
+    };
+    let frontmatter = match parse_frontmatter(&content)? {
+        Ok(frontmatter) => frontmatter,
+        Err(e) => {
+            warn!(
+                "Skipping {}, its frontmatter can't be parsed: {}",
+                path.to_str().unwrap(),
+                e
+            );
+            return None;
         }
+    };
+    Some(MarkdownFile {
+        markdown: strip_frontmatter(&content).into(),
+        path,
+        frontmatter,
+    })
+}
+
+/// Returns a note's markdown without its frontmatter, if it has any.
+pub fn strip_frontmatter(content: &str) -> &str {
+    match YAML_RE.captures(content) {
+        Some(cap) => cap.get(2).map_or("", |m| m.as_str()),
+        None => content,
     }
 }
 
+/// Parses a note's frontmatter, if it has any.
+pub fn parse_frontmatter(content: &str) -> Option<Result<Frontmatter, serde_yaml::Error>> {
+    let cap = YAML_RE.captures(content)?;
+    Some(serde_yaml::from_str(cap.get(1).map_or("", |m| m.as_str())))
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Frontmatter {
     pub title: Option<String>,
 
     #[serde(with = "utc_date", default)]
-    pub date: Option<Date<Utc>>,
-    #[serde(deserialize_with = "deserialize_tags")]
+    pub date: Option<NaiveDate>,
+    #[serde(with = "utc_date", default)]
+    pub updated: Option<NaiveDate>,
+    /// Obsidian notes often leave out `tags`, which then aren't published with `require-tag`.
+    #[serde(deserialize_with = "deserialize_tags", default)]
     pub tags: Vec<String>,
     pub author: Option<String>,
+    pub description: Option<String>,
+    pub series: Option<String>,
+    pub footnotes: Option<FootnoteStyle>,
+    /// Whether the post is included in the search index, if there is one.
+    pub search: Option<bool>,
+    /// The template to render the post with, instead of the folder's or the default one.
+    pub template: Option<String>,
+    #[serde(rename = "type", default)]
+    pub post_type: PostType,
+    /// Drafts aren't published, even with `require-tag`.
+    #[serde(default)]
+    pub draft: bool,
+}
+
+/// Whether a note is a dated post or a standalone page, e.g. an about page.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostType {
+    #[default]
+    Post,
+    /// Rendered with `page.html`, and not listed in the index, feeds, or related posts.
+    Page,
 }
 
 #[derive(Debug, Clone)]
@@ -58,9 +115,130 @@ pub struct MarkdownFile {
     markdown: String,
 }
 
+/// Everything a post refers to, collected while rendering it.
+#[derive(Default)]
+pub struct Outgoing {
+    pub assets: Vec<Asset>,
+    /// Other posts linked to, relative to the vault.
+    pub posts: Vec<PathBuf>,
+}
+
 pub struct Asset {
+    /// Relative to the vault, or absolute for generated files outside of it.
     pub source: PathBuf,
     pub target: PathBuf,
+    /// The hash of the source's content, if it was already read.
+    pub hash: Option<String>,
+}
+
+impl Asset {
+    /// Creates an asset for a file within the vault, published according to `asset-paths`. With
+    /// `asset-paths: flat`, a file with the same name as one published before gets its hashed name.
+    pub fn new(ctx: &GeneratorContext, slug: &str, source: PathBuf) -> io::Result<Asset> {
+        let file_name = source.file_name().unwrap().to_str().unwrap().to_string();
+        let read_hash =
+            || fs::read(ctx.base_dir.join(&source)).map(|content| content_hash(&content));
+        let (target, hash) = Asset::publish(ctx, slug, &source, &file_name, read_hash)?;
+        Ok(Asset {
+            source,
+            target,
+            hash,
+        })
+    }
+
+    /// Creates an asset for a file generated from another one, e.g. a resized image, which is
+    /// published as `file_name` and identified by `hash`.
+    pub fn generated(
+        ctx: &GeneratorContext,
+        slug: &str,
+        source: PathBuf,
+        file_name: &str,
+        hash: &str,
+    ) -> Asset {
+        let Ok((target, _)) = Asset::publish(ctx, slug, &source, file_name, || {
+            Ok::<_, Infallible>(hash.into())
+        });
+        Asset {
+            source,
+            target,
+            hash: None,
+        }
+    }
+
+    /// Returns where a file is published according to `asset-paths`, along with its hash if it was
+    /// needed for that. With `asset-paths: flat`, a file with the same name as one published before
+    /// gets its hashed name.
+    fn publish<E>(
+        ctx: &GeneratorContext,
+        slug: &str,
+        source: &Path,
+        file_name: &str,
+        hash: impl FnOnce() -> Result<String, E>,
+    ) -> Result<(PathBuf, Option<String>), E> {
+        Ok(match ctx.config.asset_paths {
+            AssetPaths::Hashed => {
+                let hash = hash()?;
+                (hashed_path(file_name, &hash), Some(hash))
+            }
+            AssetPaths::Flat => {
+                let target = Asset::target_path(ctx, slug, file_name, "");
+                let mut flat_assets = ctx.flat_assets.lock().unwrap();
+                match flat_assets.get(&target) {
+                    Some(published) if published != source => {
+                        let hash = hash()?;
+                        warn!(
+                            "  {} and {} are both named {}, publishing the latter as {}",
+                            published.to_str().unwrap(),
+                            source.to_str().unwrap(),
+                            file_name,
+                            hashed_path(file_name, &hash).to_str().unwrap()
+                        );
+                        (hashed_path(file_name, &hash), Some(hash))
+                    }
+                    Some(_) => (target, None),
+                    None => {
+                        flat_assets.insert(target.clone(), source.to_path_buf());
+                        (target, None)
+                    }
+                }
+            }
+            AssetPaths::Post => (Asset::target_path(ctx, slug, file_name, ""), None),
+        })
+    }
+
+    /// Returns where a file is published, relative to the output directory.
+    pub fn target_path(ctx: &GeneratorContext, slug: &str, file_name: &str, hash: &str) -> PathBuf {
+        match ctx.config.asset_paths {
+            AssetPaths::Flat => PathBuf::from(format!("static/{}", file_name)),
+            AssetPaths::Hashed => hashed_path(file_name, hash),
+            AssetPaths::Post => PathBuf::from(format!("{}/{}", slug, file_name)),
+        }
+    }
+
+    /// Returns the absolute URL the asset is published at.
+    pub fn url(&self, ctx: &GeneratorContext) -> String {
+        format!(
+            "{}/{}",
+            &ctx.config.base_url,
+            self.target.to_str().unwrap().replace('\\', "/")
+        )
+    }
+}
+
+/// The path a file is published at with `asset-paths: hashed`, e.g. `static/photo.0123456789ab.jpg`.
+fn hashed_path(file_name: &str, hash: &str) -> PathBuf {
+    let path = Path::new(file_name);
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let hash = &hash[..hash.len().min(12)];
+    match path.extension() {
+        Some(extension) => PathBuf::from(format!(
+            "static/{}.{}.{}",
+            stem,
+            hash,
+            extension.to_str().unwrap()
+        )),
+        None => PathBuf::from(format!("static/{}.{}", stem, hash)),
+    }
 }
 
 impl MarkdownFile {
@@ -84,7 +262,7 @@ impl MarkdownFile {
             .parent()
             .unwrap();
         let mut path_parts = Vec::new();
-        while path.to_str().unwrap().ne("") {
+        while !path.to_str().unwrap().is_empty() {
             path_parts.insert(
                 0,
                 MarkdownFile::slugify(path.file_name().unwrap().to_str().unwrap()),
@@ -92,150 +270,374 @@ impl MarkdownFile {
             path = path.parent().unwrap();
         }
         path_parts.push(MarkdownFile::slugify(&self.title()));
-        return path_parts.join("/");
+        path_parts.join("/")
     }
 
     fn slugify(path: &str) -> String {
-        return slug::slugify(path.replace("'", ""));
+        slug::slugify(path.replace("'", ""))
     }
 
-    pub fn render_to_html(&self, ctx: &GeneratorContext) -> (String, Vec<Asset>) {
-        let (content, footnotes) = self.split_content_and_footnotes();
+    /// Renders the note to HTML, along with its summary if it has a `<!-- more -->` marker.
+    pub fn render_to_html(
+        &self,
+        ctx: &GeneratorContext,
+    ) -> (String, Option<String>, Outgoing, PostStatistics) {
+        let markdown = transclude(&self.markdown, ctx, &self.path);
+        let markdown = render_shortcodes(&markdown, &self.path, &ctx.tera, &ctx.config.base_url);
+        let (content, footnotes) = extract_footnotes(&markdown);
 
-        let mut dest = String::with_capacity(content.len() * 2);
-        let mut assets = Vec::new();
-        MarkdownFile::render_content_to_html(&mut dest, &mut assets, &ctx, content.join("\n"));
-        MarkdownFile::render_footnotes_to_html(&mut dest, &ctx, footnotes);
-        return (dest, assets);
-    }
-
-    /// Returns the content
-    fn split_content_and_footnotes(&self) -> (Vec<String>, Vec<String>) {
-        let mut footnotes: Vec<String> = Vec::new();
-        let mut content: Vec<String> = Vec::new();
-        for line in self.markdown.lines() {
-            match line {
-                line if line.starts_with("[^") => footnotes.push(line.into()),
-                _ => content.push(MarkdownFile::separate_inline_footnote(
-                    line.into(),
-                    &mut footnotes,
-                )),
+        let mut outgoing = Outgoing::default();
+        let slug = self.slug(&ctx.base_dir);
+        let style = self.frontmatter.footnotes.unwrap_or(ctx.config.footnotes);
+        let sidenotes: HashMap<String, String> = match style {
+            FootnoteStyle::Endnotes => HashMap::new(),
+            // lists, code and the like can't be placed within a sidenote's `<span>`
+            FootnoteStyle::Sidenotes => footnotes
+                .iter()
+                .filter(|f| !has_block_content(&f.content))
+                .map(|f| {
+                    let sidenote =
+                        MarkdownFile::render_sidenote(ctx, &self.path, &slug, &mut outgoing, f);
+                    (f.label.clone(), sidenote)
+                })
+                .collect(),
+        };
+
+        let mut statistics = PostStatistics {
+            footnote_count: footnotes.len(),
+            ..Default::default()
+        };
+        for event in Parser::new_ext(&content, MarkdownFile::parser_options(true)) {
+            match event {
+                Event::Start(Tag::Image(..)) => statistics.image_count += 1,
+                Event::Start(Tag::CodeBlock(_)) => statistics.code_block_count += 1,
+                _ => {}
             }
         }
-        return (content, footnotes);
-    }
 
-    /// Separates Obsidian's inline footnotes from the text, since cmark can't handle them.
-    fn separate_inline_footnote(mut line: String, footnotes: &mut Vec<String>) -> String {
-        for cap in INLINE_FOOTNOTE.captures_iter(&line.clone()) {
-            let label = format!("[^fn-{}]", footnotes.len());
-            line = line.replace(&cap[0], label.as_str());
+        let mut dest = String::with_capacity(content.len() * 2);
+        let (references, summary) = MarkdownFile::render_content_to_html(
+            &mut dest,
+            &mut outgoing,
+            ctx,
+            &self.path,
+            &slug,
+            content,
+            &sidenotes,
+        );
+        let endnotes = footnotes
+            .into_iter()
+            .filter(|f| !references.contains(&f.label) || !sidenotes.contains_key(&f.label))
+            .collect();
+        MarkdownFile::render_footnotes_to_html(
+            &mut dest,
+            ctx,
+            &self.path,
+            &slug,
+            &mut outgoing,
+            endnotes,
+            &references,
+        );
 
-            let footnoted = format!("{}: {}", label, &cap[1]);
-            footnotes.push(footnoted);
-        }
-        return line;
+        statistics.word_count = word_count(&plain_text(&dest));
+        statistics.reading_time = statistics
+            .word_count
+            .div_ceil(ctx.config.words_per_minute.max(1));
+        (dest, summary, outgoing, statistics)
     }
 
-    /// Writes the page contents to HTML
+    /// Writes the page contents to HTML, returning the footnote labels in order of reference and the
+    /// summary before `<!-- more -->`. Footnotes with a rendered sidenote are placed next to their
+    /// reference, and only the remaining endnotes are numbered, matching their list.
     fn render_content_to_html(
-        mut dest: &mut String,
-        assets: &mut Vec<Asset>,
+        dest: &mut String,
+        outgoing: &mut Outgoing,
         ctx: &GeneratorContext,
+        note: &Path,
+        slug: &str,
         content: String,
-    ) {
-        let mut footnote_no = 0;
+        sidenotes: &HashMap<String, String>,
+    ) -> (Vec<String>, Option<String>) {
         let parser = Parser::new_ext(&content, MarkdownFile::parser_options(true));
         let events = parser.map(|event| match event {
-            Event::FootnoteReference(name) => {
-                footnote_no += 1;
-                let formatted = format!(
-                    "<sup class=\"fn\"><a id=\"{}-back\" href=\"#{}\">[{}]</a></sup>",
-                    name, name, footnote_no
-                );
-                Event::Html(formatted.into())
-            }
-            Event::Start(Tag::Heading(level, _fragment, _classes)) => {
-                Event::Html(format!("<{}>", MarkdownFile::increase_heading_level(level)).into())
-            }
-            Event::End(Tag::Heading(level, _fragment, _classes)) => {
-                Event::Html(format!("</{}>", MarkdownFile::increase_heading_level(level)).into())
-            }
             Event::Start(Tag::Link(link_type, dest, title)) => {
-                rewrite_relative_url(&ctx, link_type, dest, title)
-            }
-            Event::Start(Tag::Image(link_type, dest, title)) => {
-                if is_relative_url(dest.to_string()) {
-                    let source = PathBuf::from(dest.into_string());
-                    let relative_target_path =
-                        format!("static/{}", source.file_name().unwrap().to_str().unwrap());
-                    let absolute_url =
-                        format!("{}/{}", &ctx.config.base_url, &relative_target_path);
-                    assets.push(Asset {
-                        source: source.into(),
-                        target: PathBuf::from(relative_target_path.clone()),
-                    });
-                    Event::Start(Tag::Image(link_type, absolute_url.into(), title))
-                } else {
-                    Event::Start(Tag::Image(link_type, dest, title))
-                }
+                rewrite_relative_url(ctx, note, slug, outgoing, link_type, dest, title)
             }
             _ => event,
         });
-        html::push_html(&mut dest, events);
+        let events = MarkdownFile::render_headings(events);
+        let events = ctx.diagrams.render_code_blocks(events.into_iter());
+        let events = MarkdownFile::render_images(ctx, note, slug, &mut outgoing.assets, events);
+        let summary = summary_before_marker(&events);
+        let (events, references) = MarkdownFile::render_footnote_references(events, sidenotes);
+        html::push_html(dest, events.into_iter());
+        (references, summary)
+    }
+
+    /// Renders references to footnotes, returning the footnote labels in order of reference.
+    fn render_footnote_references<'a>(
+        events: Vec<Event<'a>>,
+        sidenotes: &HashMap<String, String>,
+    ) -> (Vec<Event<'a>>, Vec<String>) {
+        let mut references = Vec::new();
+        let mut endnotes: Vec<String> = Vec::new();
+        let events = events
+            .into_iter()
+            .map(|event| match event {
+                Event::FootnoteReference(name) => {
+                    references.push(name.to_string());
+                    let formatted = match sidenotes.get(name.as_ref()) {
+                        Some(sidenote) => format!(
+                            "<label for=\"sn-{}-{}\" class=\"margin-toggle sidenote-number\"></label>\
+                             <input type=\"checkbox\" id=\"sn-{}-{}\" class=\"margin-toggle\"/>\
+                             <span class=\"sidenote\">{}</span>",
+                            name,
+                            references.len(),
+                            name,
+                            references.len(),
+                            sidenote
+                        ),
+                        None => {
+                            let number = match endnotes.iter().position(|e| e == name.as_ref()) {
+                                Some(position) => position + 1,
+                                None => {
+                                    endnotes.push(name.to_string());
+                                    endnotes.len()
+                                }
+                            };
+                            format!(
+                                "<sup class=\"fn\"><a id=\"{}-back\" href=\"#{}\">[{}]</a></sup>",
+                                name, name, number
+                            )
+                        }
+                    };
+                    Event::Html(formatted.into())
+                }
+                _ => event,
+            })
+            .collect();
+        (events, references)
+    }
+
+    /// Moves headings down by one level, since the post's title is the only `<h1>`, and gives each
+    /// heading an `id` derived from its text to link to.
+    fn render_headings<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+        let mut result = Vec::new();
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        let mut heading: Option<(Vec<Event<'a>>, String)> = None;
+        for event in events {
+            match (&mut heading, event) {
+                (None, Event::Start(Tag::Heading(..))) => {
+                    heading = Some((Vec::new(), String::new()))
+                }
+                (Some((buffered, text)), Event::End(Tag::Heading(level, _, _))) => {
+                    let mut id = heading_id(text);
+                    let count = ids.entry(id.clone()).or_insert(0);
+                    if *count > 0 {
+                        id = format!("{}-{}", id, count);
+                    }
+                    *count += 1;
+
+                    let level = MarkdownFile::increase_heading_level(level);
+                    result.push(Event::Html(format!("<{} id=\"{}\">", level, id).into()));
+                    result.append(buffered);
+                    result.push(Event::Html(format!("</{}>\n", level).into()));
+                    heading = None;
+                }
+                (Some((buffered, text)), event) => {
+                    if let Event::Text(t) | Event::Code(t) = &event {
+                        text.push_str(t);
+                    }
+                    buffered.push(event);
+                }
+                (None, event) => result.push(event),
+            }
+        }
+        result
+    }
+
+    /// Rewrites relative image paths to copied assets, resizing images if configured. Files within
+    /// hidden or `_`-prefixed folders are left unresolved, since they aren't published.
+    fn render_images<'a>(
+        ctx: &GeneratorContext,
+        note: &Path,
+        slug: &str,
+        assets: &mut Vec<Asset>,
+        events: Vec<Event<'a>>,
+    ) -> Vec<Event<'a>> {
+        let mut result = Vec::with_capacity(events.len());
+        let mut image: Option<Vec<Event<'a>>> = None;
+        for event in events {
+            match (&mut image, event) {
+                (None, Event::Start(Tag::Image(link_type, dest, title))) => {
+                    image = Some(vec![Event::Start(Tag::Image(link_type, dest, title))]);
+                }
+                (Some(buffered), Event::End(Tag::Image(link_type, dest, title))) => {
+                    let source = match resolve_link(ctx, note, &dest) {
+                        Some(link) if !dir::is_skipped(&link.path) => link.path,
+                        skipped => {
+                            if skipped.is_some() {
+                                warn!(
+                                    "Not embedding {}, files within hidden or `_`-prefixed folders aren't published",
+                                    dest.to_string()
+                                );
+                            }
+                            result.append(buffered);
+                            result.push(Event::End(Tag::Image(link_type, dest, title)));
+                            image = None;
+                            continue;
+                        }
+                    };
+                    if let Some(responsive) = process_image(ctx, slug, &source) {
+                        let alt: String = buffered
+                            .iter()
+                            .filter_map(|e| match e {
+                                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                                _ => None,
+                            })
+                            .collect();
+                        result.push(Event::Html(responsive.to_html(ctx, &alt, &title).into()));
+                        assets.extend(responsive.variants.into_iter().map(|v| v.asset));
+                    } else {
+                        let asset = match Asset::new(ctx, slug, source.clone()) {
+                            Ok(asset) => asset,
+                            Err(e) => {
+                                warn!("Could not read {}: {}", source.to_str().unwrap(), e);
+                                result.append(buffered);
+                                result.push(Event::End(Tag::Image(link_type, dest, title)));
+                                image = None;
+                                continue;
+                            }
+                        };
+                        if let Some(element) = media_element(&source) {
+                            let mut fallback = String::new();
+                            for event in buffered.iter() {
+                                if let Event::Text(text) | Event::Code(text) = event {
+                                    escape_html(&mut fallback, text).unwrap();
+                                }
+                            }
+                            result.push(Event::Html(
+                                format!(
+                                    "<{} controls src=\"{}\">{}</{}>",
+                                    element,
+                                    asset.url(ctx),
+                                    fallback,
+                                    element
+                                )
+                                .into(),
+                            ));
+                            assets.push(asset);
+                        } else {
+                            let absolute_url = asset.url(ctx);
+                            assets.push(asset);
+                            buffered[0] = Event::Start(Tag::Image(
+                                link_type,
+                                absolute_url.clone().into(),
+                                title.clone(),
+                            ));
+                            result.append(buffered);
+                            result.push(Event::End(Tag::Image(
+                                link_type,
+                                absolute_url.into(),
+                                title,
+                            )));
+                        }
+                    }
+                    image = None;
+                }
+                (Some(buffered), event) => buffered.push(event),
+                (None, event) => result.push(event),
+            }
+        }
+        result
     }
 
     fn increase_heading_level(level: HeadingLevel) -> HeadingLevel {
-        return match level {
+        match level {
             HeadingLevel::H1 => HeadingLevel::H2,
             HeadingLevel::H2 => HeadingLevel::H3,
             HeadingLevel::H3 => HeadingLevel::H4,
             HeadingLevel::H4 => HeadingLevel::H5,
             HeadingLevel::H5 => HeadingLevel::H6,
             other => panic!("Could not increase heading from level {}", other),
-        };
+        }
     }
 
-    /// Writes the footnotes to HTML
+    /// Writes the footnotes to HTML, in the order they're referenced in the text.
     fn render_footnotes_to_html(
-        mut dest: &mut String,
+        dest: &mut String,
         ctx: &GeneratorContext,
-        footnotes: Vec<String>,
+        note: &Path,
+        slug: &str,
+        outgoing: &mut Outgoing,
+        mut footnotes: Vec<Footnote>,
+        references: &[String],
     ) {
-        let mut formatted_footnotes = String::new();
-        if !footnotes.is_empty() {
-            formatted_footnotes = format!(
-                "---\n{}",
-                footnotes
-                    .iter()
-                    .map(|f| MarkdownFile::format_footnote_li(f))
-                    .collect::<Vec<String>>()
-                    .join("\n")
+        if footnotes.is_empty() {
+            return;
+        }
+        footnotes.sort_by_key(|f| {
+            references
+                .iter()
+                .position(|r| r == &f.label)
+                .unwrap_or(references.len())
+        });
+
+        dest.push_str("<hr />\n<ol>\n");
+        for footnote in footnotes {
+            // Single paragraphs are rendered like tight list items, the back-link goes last
+            let mut content = MarkdownFile::render_footnote(ctx, note, slug, outgoing, &footnote);
+            let back_link = format!(
+                " <a class=\"fn-back\" href=\"#{}-back\">↩</a>",
+                footnote.label
             );
+            if content.starts_with("<p>")
+                && content.ends_with("</p>")
+                && content.matches("<p>").count() == 1
+            {
+                content = content["<p>".len()..].replacen("</p>", &back_link, 1);
+            } else if content.ends_with("</p>") {
+                content.insert_str(content.len() - "</p>".len(), &back_link);
+            } else {
+                content.push_str(&back_link);
+            }
+            dest.push_str(&format!("<li id=\"{}\">{}</li>\n", footnote.label, content));
         }
-        let mut footnote_no = 0;
-        let parser = Parser::new_ext(&formatted_footnotes, MarkdownFile::parser_options(false));
+        dest.push_str("</ol>\n");
+    }
+
+    /// Renders a footnote's content, rewriting links the same way as in the text.
+    fn render_footnote(
+        ctx: &GeneratorContext,
+        note: &Path,
+        slug: &str,
+        outgoing: &mut Outgoing,
+        footnote: &Footnote,
+    ) -> String {
+        let mut content = String::new();
+        let parser = Parser::new_ext(&footnote.content, MarkdownFile::parser_options(false));
         let events = parser.map(|event| match event {
-            Event::Start(Tag::Item) => {
-                let cap = NORMAL_FOOTNOTE.captures(&footnotes[footnote_no]).unwrap();
-                footnote_no += 1;
-                Event::Html(format!("<li id=\"{}\">", &cap[1]).into())
-            }
             Event::Start(Tag::Link(link_type, dest, title)) => {
-                rewrite_relative_url(&ctx, link_type, dest, title)
+                rewrite_relative_url(ctx, note, slug, outgoing, link_type, dest, title)
             }
             _ => event,
         });
-        html::push_html(&mut dest, events);
+        html::push_html(&mut content, events);
+        content.trim_end().to_string()
     }
 
-    /// Formats footnotes as lists and to include a back-link.
-    fn format_footnote_li(line: &String) -> String {
-        let cap = NORMAL_FOOTNOTE.captures(line).unwrap();
-        return format!(
-            "1. {} <a class=\"fn-back\" href=\"#{}-back\">↩</a>",
-            &cap[2], &cap[1]
-        );
+    /// Renders a footnote to be placed within a `<span>`, with paragraphs separated by line breaks.
+    fn render_sidenote(
+        ctx: &GeneratorContext,
+        note: &Path,
+        slug: &str,
+        outgoing: &mut Outgoing,
+        footnote: &Footnote,
+    ) -> String {
+        MarkdownFile::render_footnote(ctx, note, slug, outgoing, footnote)
+            .trim_start_matches("<p>")
+            .trim_end_matches("</p>")
+            .replace("</p>\n<p>", "<br /><br />")
     }
 
     /// Returns the default parser options, optionally including footnotes.
@@ -249,35 +651,252 @@ impl MarkdownFile {
             options.insert(Options::ENABLE_FOOTNOTES);
         }
 
-        return options;
+        options
     }
 }
 
-fn is_relative_url(dest: String) -> bool {
-    return !dest.contains("://");
+/// Returns the HTML element embedding an audio or video file, if the file is one.
+fn media_element(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "mp3" | "wav" | "ogg" | "oga" | "m4a" | "flac" | "opus" => Some("audio"),
+        "mp4" | "webm" | "ogv" | "mov" | "m4v" => Some("video"),
+        _ => None,
+    }
 }
 
+/// Renders markdown outside of a post, e.g. from a template, resolving links relative to the vault.
+/// Returns the HTML and any assets linked to, which are published within `slug` if needed.
+pub fn render_markdown(ctx: &GeneratorContext, markdown: &str, slug: &str) -> (String, Vec<Asset>) {
+    let mut outgoing = Outgoing::default();
+    let mut html = String::new();
+    let parser = Parser::new_ext(markdown, MarkdownFile::parser_options(false));
+    let events = parser.map(|event| match event {
+        Event::Start(Tag::Link(link_type, dest, title)) => rewrite_relative_url(
+            ctx,
+            &ctx.base_dir,
+            slug,
+            &mut outgoing,
+            link_type,
+            dest,
+            title,
+        ),
+        _ => event,
+    });
+    html::push_html(&mut html, events);
+    (html, outgoing.assets)
+}
+
+/// Rewrites links to other posts to their published URL. Links to other files within the vault,
+/// e.g. PDFs, are copied as assets.
 fn rewrite_relative_url<'a>(
     ctx: &'a GeneratorContext,
+    note: &Path,
+    slug: &str,
+    outgoing: &mut Outgoing,
     link_type: LinkType,
     dest: CowStr<'a>,
     title: CowStr<'a>,
 ) -> Event<'a> {
-    let mut target = dest.clone();
-    if is_relative_url(dest.to_string()) {
-        // Obsidian-ish quirk: whitespace is replaced by %20
-        let replaced = dest.replace("%20", " ").to_string();
-
-        let post = &ctx
-            .posts
-            .iter()
-            .find(|(_, post)| replaced == post.original_file_name.replace("\\", "/"));
-        if let Some((_, post)) = post {
-            target = CowStr::from(format!("{}/{}", &ctx.config.base_url, &post.relative_url));
-        } else {
-            warn!("Unable to resolve relative link: {}", dest.to_string());
+    let target = match resolve_url(ctx, note, slug, &dest) {
+        Some((url, LinkTarget::Post(path))) => {
+            outgoing.posts.push(path);
+            CowStr::from(url)
+        }
+        Some((url, LinkTarget::Asset(asset))) => {
+            outgoing.assets.push(asset);
+            CowStr::from(url)
+        }
+        None => {
+            match resolve_link(ctx, note, &dest) {
+                Some(link) if dir::is_skipped(&link.path) => warn!(
+                    "Not linking to {}, files within hidden or `_`-prefixed folders aren't published",
+                    dest.to_string()
+                ),
+                _ if is_relative_url(&dest) => {
+                    warn!("Unable to resolve relative link: {}", dest.to_string())
+                }
+                _ => {}
+            }
+            dest
         }
     };
 
     Event::Start(Tag::Link(link_type, target, title))
 }
+
+/// What a link within the vault points to.
+pub enum LinkTarget {
+    /// A published post, relative to the vault.
+    Post(PathBuf),
+    /// Any other file, which needs to be copied.
+    Asset(Asset),
+}
+
+/// Resolves a link within a note to the URL it's published at. Links to other posts point to the
+/// post, links to other files within the vault, e.g. PDFs, to an asset.
+pub fn resolve_url(
+    ctx: &GeneratorContext,
+    note: &Path,
+    slug: &str,
+    dest: &str,
+) -> Option<(String, LinkTarget)> {
+    let link = resolve_link(ctx, note, dest)?;
+    let post = ctx
+        .posts
+        .iter()
+        .find(|(file, _)| file.path.strip_prefix(&ctx.base_dir).unwrap() == link.path);
+    if let Some((_, post)) = post {
+        let url = format!(
+            "{}/{}{}",
+            &ctx.config.base_url, &post.relative_url, link.suffix
+        );
+        Some((url, LinkTarget::Post(link.path)))
+    } else if link.path.extension().is_none_or(|e| e != "md")
+        && !dir::is_skipped(&link.path)
+        && ctx.base_dir.join(&link.path).is_file()
+    {
+        let asset = match Asset::new(ctx, slug, link.path) {
+            Ok(asset) => asset,
+            Err(e) => {
+                warn!("Could not read {}: {}", dest, e);
+                return None;
+            }
+        };
+        let url = format!("{}{}", asset.url(ctx), link.suffix);
+        Some((url, LinkTarget::Asset(asset)))
+    } else {
+        None
+    }
+}
+
+/// The HTML before a `<!-- more -->` marker without any footnote references, with any elements the
+/// marker is within closed.
+fn summary_before_marker(events: &[Event]) -> Option<String> {
+    let index = events
+        .iter()
+        .position(|event| matches!(event, Event::Html(html) if MORE_MARKER.is_match(html)))?;
+    let mut open = Vec::new();
+    for event in &events[..index] {
+        match event {
+            Event::Start(tag) => open.push(tag.clone()),
+            Event::End(_) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+
+    // footnotes aren't shown along with the summary
+    let events = events[..index]
+        .iter()
+        .filter(|event| !matches!(event, Event::FootnoteReference(_)))
+        .cloned();
+    let mut summary = String::new();
+    let closing = open.into_iter().rev().map(Event::End);
+    html::push_html(&mut summary, events.chain(closing));
+    Some(summary.trim_end().to_string())
+}
+
+/// Turns a heading's text into an `id`, e.g. `Why Campfire?` into `why-campfire`.
+fn heading_id(text: &str) -> String {
+    let id = text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+    if id.is_empty() {
+        "section".into()
+    } else {
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_frontmatter, summary_before_marker, MarkdownFile};
+    use crate::context::{test_context, GeneratorContext};
+    use pulldown_cmark::{Event, Parser};
+
+    fn note(ctx: &GeneratorContext, markdown: &str) -> MarkdownFile {
+        MarkdownFile {
+            path: ctx.base_dir.join("Note.md"),
+            frontmatter: serde_yaml::from_str("tags: [published]").unwrap(),
+            markdown: markdown.into(),
+        }
+    }
+
+    fn summary(markdown: &str) -> Option<String> {
+        let events: Vec<Event> = Parser::new(markdown).collect();
+        summary_before_marker(&events)
+    }
+
+    #[test]
+    fn summary_before_marker_variants() {
+        assert_eq!(
+            summary("One\n\n<!-- more -->\n\nTwo"),
+            Some("<p>One</p>".into())
+        );
+        assert_eq!(
+            summary("One\n\n<!--more-->\n\nTwo"),
+            Some("<p>One</p>".into())
+        );
+        assert_eq!(
+            summary("One\n\n<!--  MORE -->\n\nTwo"),
+            Some("<p>One</p>".into())
+        );
+        assert_eq!(summary("One <!-- more --> two"), Some("<p>One </p>".into()));
+        assert_eq!(summary("<!-- a comment -->\n\nOne"), None);
+    }
+
+    #[test]
+    fn summary_closes_open_elements() {
+        assert_eq!(
+            summary("> - *One <!-- more --> two*\n> - Three"),
+            Some("<blockquote>\n<ul>\n<li><em>One </em></li>\n</ul>\n</blockquote>".into())
+        );
+    }
+
+    #[test]
+    fn number_only_endnotes_with_sidenotes() {
+        let ctx = test_context("name: blog\nrequire-tag: published\nfootnotes: sidenotes\n");
+        let markdown =
+            "A[^a] B[^b] C[^c]\n\n[^a]: Aside\n[^b]:\n    - A list\n[^c]:\n    - Another";
+        let (html, ..) = note(&ctx, markdown).render_to_html(&ctx);
+        assert!(html.contains("<span class=\"sidenote\">Aside</span>"));
+        assert!(html.contains("<a id=\"b-back\" href=\"#b\">[1]</a>"));
+        assert!(html.contains("<a id=\"c-back\" href=\"#c\">[2]</a>"));
+        assert!(html.contains("<ol>\n<li id=\"b\">"));
+    }
+
+    #[test]
+    fn dont_embed_skipped_files() {
+        let ctx = test_context("name: blog\nrequire-tag: published\n");
+        let markdown = "![secret](_private/secret.png) ![audio](.hidden/a.mp3)";
+        let (html, _, outgoing, _) = note(&ctx, markdown).render_to_html(&ctx);
+        assert_eq!(
+            html,
+            "<p><img src=\"_private/secret.png\" alt=\"secret\" /> \
+             <img src=\".hidden/a.mp3\" alt=\"audio\" /></p>\n"
+        );
+        assert!(outgoing.assets.is_empty());
+    }
+
+    #[test]
+    fn summary_without_footnotes() {
+        let ctx = test_context("name: blog\nrequire-tag: published\nfootnotes: sidenotes\n");
+        let markdown = "One[^a] two^[inline].\n\n<!-- more -->\n\nThree\n\n[^a]:\n    - A list";
+        let (_, summary, ..) = note(&ctx, markdown).render_to_html(&ctx);
+        assert_eq!(summary, Some("<p>One two.</p>".into()));
+    }
+
+    #[test]
+    fn frontmatter_without_tags() {
+        let frontmatter = parse_frontmatter("---\ntitle: A\n---\nText").unwrap();
+        assert!(frontmatter.unwrap().tags.is_empty());
+        assert!(parse_frontmatter("---\ntitle: [A\n---\nText")
+            .unwrap()
+            .is_err());
+    }
+}
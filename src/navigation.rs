@@ -0,0 +1,166 @@
+use crate::config::Navigation;
+use crate::context::{GeneratorContext, PostContext, PostLink, SeriesContext, TagContext};
+use crate::markdown::PostType;
+use log::info;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Folders the tag and series pages are generated into, which no post may be published within.
+const GENERATED_FOLDERS: [&str; 2] = ["tags", "series"];
+
+/// Reports posts that would be published within `tags/` or `series/`, e.g. since they're in a vault
+/// folder of that name, as they'd overwrite or be mixed up with the tag and series pages.
+pub fn check_generated_folders(ctx: &GeneratorContext) -> Result<(), String> {
+    let collisions: Vec<String> = ctx
+        .posts
+        .iter()
+        .filter(|(_, post)| {
+            let folder = post.relative_url.split('/').next().unwrap_or_default();
+            GENERATED_FOLDERS.contains(&folder)
+        })
+        .map(|(file, post)| {
+            format!(
+                "{} would be published at {}",
+                file.path
+                    .strip_prefix(&ctx.base_dir)
+                    .unwrap()
+                    .to_str()
+                    .unwrap(),
+                post.relative_url
+            )
+        })
+        .collect();
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "{}; tags/ and series/ are reserved for tag and series pages, rename the folders or notes",
+        collisions.join(", ")
+    ))
+}
+
+/// Links each post to the next older and newer post, optionally only within the same folder or
+/// with the same (first) tag.
+pub fn add_previous_and_next(ctx: &mut GeneratorContext) {
+    let navigation = ctx.config.navigation;
+    let group = |post: &PostContext| -> Option<String> {
+        if post.post_type == PostType::Page {
+            return None;
+        }
+        match navigation {
+            Navigation::All => Some("".into()),
+            Navigation::Section => Path::new(&post.original_file_name)
+                .parent()
+                .map(|section| section.to_str().unwrap().into()),
+            Navigation::Tag => post.tags.first().cloned(),
+        }
+    };
+
+    // posts are sorted from newest to oldest
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, (_, post)) in ctx.posts.iter().enumerate() {
+        if let Some(group) = group(post) {
+            groups.entry(group).or_default().push(i);
+        }
+    }
+
+    let links: Vec<PostLink> = ctx.posts.iter().map(|(_, p)| p.into()).collect();
+    for posts in groups.values() {
+        for (i, &post) in posts.iter().enumerate() {
+            let (_, post) = &mut ctx.posts[post];
+            post.next = i.checked_sub(1).map(|next| links[posts[next]].clone());
+            post.previous = posts.get(i + 1).map(|previous| links[*previous].clone());
+        }
+    }
+}
+
+/// Groups posts with the same `series` in their frontmatter, from oldest to newest.
+pub fn add_series(ctx: &mut GeneratorContext) {
+    let mut series: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, (file, post)) in ctx.posts.iter().enumerate() {
+        if post.post_type == PostType::Page {
+            continue;
+        }
+        if let Some(name) = &file.frontmatter.series {
+            series.entry(name.clone()).or_default().push(i);
+        }
+    }
+
+    for (name, mut posts) in series {
+        posts.sort_by(|a, b| {
+            let (a, b) = (&ctx.posts[*a].1, &ctx.posts[*b].1);
+            a.date
+                .cmp(&b.date)
+                .then_with(|| a.relative_url.cmp(&b.relative_url))
+        });
+        let parts: Vec<PostLink> = posts.iter().map(|i| (&ctx.posts[*i].1).into()).collect();
+        for (index, post) in posts.into_iter().enumerate() {
+            ctx.posts[post].1.series = Some(SeriesContext {
+                relative_url: format!("series/{}/", slug::slugify(&name)),
+                name: name.clone(),
+                index: index + 1,
+                parts: parts.clone(),
+            });
+        }
+    }
+}
+
+/// Writes a landing page listing the parts of each series.
+pub fn generate_series_pages(ctx: &GeneratorContext) {
+    let mut series: BTreeMap<&str, &SeriesContext> = BTreeMap::new();
+    for (_, post) in &ctx.posts {
+        if let Some(s) = &post.series {
+            series.insert(&s.relative_url, s);
+        }
+    }
+
+    for (relative_url, series) in series {
+        let mut context = ctx.global_context.clone();
+        context.insert("series", series);
+
+        let dir = ctx.output_dir.join(relative_url);
+        info!("Generating {}", dir.to_str().unwrap());
+        let rendered = ctx.tera.render("series.html", &context).unwrap();
+        fs::create_dir_all(&dir).expect("Failed to create directory");
+        fs::write(dir.join("index.html"), rendered).expect("Failed to write series");
+    }
+}
+
+/// All tags of posts, sorted by name, each with its posts from newest to oldest.
+pub fn tags(ctx: &GeneratorContext) -> Vec<TagContext> {
+    let mut tags: BTreeMap<&str, Vec<PostLink>> = BTreeMap::new();
+    for (_, post) in &ctx.posts {
+        if post.post_type == PostType::Post {
+            for tag in &post.tags {
+                tags.entry(tag).or_default().push(post.into());
+            }
+        }
+    }
+    tags.into_iter()
+        .map(|(name, posts)| TagContext {
+            relative_url: format!("tags/{}/", slug::slugify(name)),
+            name: name.into(),
+            posts,
+        })
+        .collect()
+}
+
+/// Writes a page listing all tags, and a page for each tag listing its posts.
+pub fn generate_tag_pages(ctx: &GeneratorContext) {
+    let tags_dir = ctx.output_dir.join("tags");
+    let rendered = ctx.tera.render("tags.html", &ctx.global_context).unwrap();
+    fs::create_dir_all(&tags_dir).expect("Failed to create directory");
+    fs::write(tags_dir.join("index.html"), rendered).expect("Failed to write tags");
+
+    for tag in tags(ctx) {
+        let mut context = ctx.global_context.clone();
+        context.insert("tag", &tag);
+
+        let dir = ctx.output_dir.join(&tag.relative_url);
+        info!("Generating {}", dir.to_str().unwrap());
+        let rendered = ctx.tera.render("tag.html", &context).unwrap();
+        fs::create_dir_all(&dir).expect("Failed to create directory");
+        fs::write(dir.join("index.html"), rendered).expect("Failed to write tag");
+    }
+}
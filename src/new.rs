@@ -0,0 +1,128 @@
+use crate::config::Config;
+use crate::markdown::parse_frontmatter;
+use chrono::Local;
+use log::{debug, info};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera};
+
+/// Characters Obsidian doesn't allow in file names.
+const FORBIDDEN_CHARACTERS: &[char] = &[
+    '\\', '/', ':', '*', '?', '"', '<', '>', '|', '#', '^', '[', ']',
+];
+
+/// Options for a new note.
+pub struct NewNote<'a> {
+    pub title: &'a str,
+    /// The folder within the vault to create the note in.
+    pub dir: Option<&'a str>,
+    pub tags: Vec<String>,
+    /// Whether the note is tagged with `require-tag`.
+    pub publish: bool,
+    /// The name of an archetype in `.campfire/archetypes`, without `.md`.
+    pub archetype: Option<&'a str>,
+}
+
+/// Writes a new note with its frontmatter filled in from an archetype, which is
+/// `.campfire/archetypes/<archetype>.md`, `<first folder>.md`, `default.md` or a built-in one.
+pub fn new_note(
+    base_dir: &Path,
+    config: &Config,
+    note: NewNote,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let file_name: String = note
+        .title
+        .chars()
+        .filter(|c| !FORBIDDEN_CHARACTERS.contains(c))
+        .collect();
+    let file_name = file_name.trim();
+    if file_name.is_empty() {
+        return Err(format!("Can't name a note after {:?}", note.title).into());
+    }
+
+    let dir = base_dir.join(note.dir.unwrap_or(""));
+    let path = dir.join(format!("{}.md", file_name));
+    if path.exists() {
+        return Err(format!("{} already exists", path.to_str().unwrap()).into());
+    }
+
+    let mut tags = note.tags.clone();
+    if note.publish && !tags.contains(&config.require_tag) {
+        tags.insert(0, config.require_tag.clone());
+    }
+
+    let mut context = Context::new();
+    context.insert("title", note.title);
+    context.insert("date", &Local::now().naive_local().date().to_string());
+    context.insert("author", &config.author);
+    context.insert("tags", &tags);
+    context.insert("require_tag", &config.require_tag);
+
+    let (archetype_name, archetype) = archetype(base_dir, &note)?;
+    let content = Tera::one_off(&archetype, &context, false)?;
+    match parse_frontmatter(&content) {
+        Some(Ok(_)) => {}
+        Some(Err(e)) => {
+            return Err(format!("{} has invalid frontmatter: {}", archetype_name, e).into())
+        }
+        None => return Err(format!("{} has no frontmatter", archetype_name).into()),
+    }
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, content)?;
+    info!("Created {}", path.to_str().unwrap());
+    Ok(path)
+}
+
+/// The archetype's name and template.
+fn archetype(base_dir: &Path, note: &NewNote) -> Result<(String, String), Box<dyn Error>> {
+    let archetype_dir = base_dir.join(".campfire").join("archetypes");
+    if let Some(name) = note.archetype {
+        let file = archetype_dir.join(format!("{}.md", name));
+        if !file.is_file() {
+            return Err(format!("Archetype not found: {}", file.to_str().unwrap()).into());
+        }
+        return Ok((file.to_str().unwrap().into(), fs::read_to_string(file)?));
+    }
+
+    let section = note
+        .dir
+        .and_then(|dir| Path::new(dir).components().next())
+        .and_then(|section| section.as_os_str().to_str());
+    let candidates = section.into_iter().chain(Some("default"));
+    for name in candidates {
+        let file = archetype_dir.join(format!("{}.md", name));
+        if file.is_file() {
+            debug!("Using archetype {}", file.to_str().unwrap());
+            return Ok((file.to_str().unwrap().into(), fs::read_to_string(file)?));
+        }
+    }
+    Ok((
+        "The built-in archetype".into(),
+        include_str!("templates/archetype.md").into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::markdown::parse_frontmatter;
+    use tera::{Context, Tera};
+
+    #[test]
+    fn builtin_archetype_parses() {
+        let mut context = Context::new();
+        context.insert("title", "Quotes: \"a\" and 'b'");
+        context.insert("date", "2022-08-01");
+        context.insert("author", "Some Person");
+        context.insert("tags", &vec!["published", "rust"]);
+        let content =
+            Tera::one_off(include_str!("templates/archetype.md"), &context, false).unwrap();
+
+        let frontmatter = parse_frontmatter(&content).unwrap().unwrap();
+        assert_eq!(frontmatter.title.unwrap(), "Quotes: \"a\" and 'b'");
+        assert_eq!(frontmatter.date.unwrap().to_string(), "2022-08-01");
+        assert_eq!(frontmatter.author.unwrap(), "Some Person");
+        assert_eq!(frontmatter.tags, vec!["published", "rust"]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+/// Score for each tag two posts have in common.
+const SHARED_TAG_SCORE: u32 = 1;
+/// Score for a post linking to the other one, counted once per direction.
+const DIRECT_LINK_SCORE: u32 = 3;
+/// Score for each post linking to both posts.
+const CO_CITATION_SCORE: u32 = 2;
+
+/// Tags with more posts than this, e.g. a blog's main topic, and posts linking to more posts than
+/// this, e.g. an index of all posts, say too little about two posts being related to be counted.
+const MAX_SHARED_POSTS: usize = 100;
+
+/// Ranks posts by how closely they're related to each other, returning up to `count` indices of
+/// related posts for each post.
+///
+/// `tags` and `links` hold each post's tags and the indices of the posts it links to. Ties are
+/// broken by `order`, e.g. the posts' URLs, so the result doesn't depend on the order in which
+/// posts were found.
+pub fn related_posts<T: Ord>(
+    tags: &[Vec<String>],
+    links: &[Vec<usize>],
+    order: &[T],
+    count: usize,
+) -> Vec<Vec<usize>> {
+    if count == 0 {
+        return vec![vec![]; tags.len()];
+    }
+
+    let mut posts_by_tag: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (post, post_tags) in tags.iter().enumerate() {
+        for tag in post_tags {
+            posts_by_tag.entry(tag).or_default().push(post);
+        }
+    }
+    let links: Vec<Vec<usize>> = links
+        .iter()
+        .enumerate()
+        .map(|(post, targets)| {
+            let mut targets = targets.clone();
+            targets.sort_unstable();
+            targets.dedup();
+            targets.retain(|&target| target != post);
+            targets
+        })
+        .collect();
+    let mut linked_from: Vec<Vec<usize>> = vec![vec![]; links.len()];
+    for (post, targets) in links.iter().enumerate() {
+        for &target in targets {
+            linked_from[target].push(post);
+        }
+    }
+
+    // scores are only kept for one post at a time, rather than for every pair of posts
+    (0..tags.len())
+        .map(|post| {
+            let mut scores: HashMap<usize, u32> = HashMap::new();
+            for tag in &tags[post] {
+                let posts = &posts_by_tag[tag.as_str()];
+                if posts.len() <= MAX_SHARED_POSTS {
+                    for &other in posts {
+                        *scores.entry(other).or_default() += SHARED_TAG_SCORE;
+                    }
+                }
+            }
+            for &other in links[post].iter().chain(&linked_from[post]) {
+                *scores.entry(other).or_default() += DIRECT_LINK_SCORE;
+            }
+            for &citing in &linked_from[post] {
+                if links[citing].len() <= MAX_SHARED_POSTS {
+                    for &other in &links[citing] {
+                        *scores.entry(other).or_default() += CO_CITATION_SCORE;
+                    }
+                }
+            }
+            scores.remove(&post);
+
+            let mut ranked: Vec<(usize, u32)> = scores.into_iter().collect();
+            ranked.sort_by(|(a, a_score), (b, b_score)| {
+                b_score.cmp(a_score).then_with(|| order[*a].cmp(&order[*b]))
+            });
+            ranked
+                .into_iter()
+                .take(count)
+                .map(|(post, _)| post)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::related_posts;
+
+    #[test]
+    fn rank_by_tags_and_links() {
+        let tags = vec![
+            vec!["rust".to_string(), "web".to_string()],
+            vec!["rust".to_string(), "web".to_string()],
+            vec!["rust".to_string()],
+            vec![],
+            vec![],
+        ];
+        // 3 links to 2, 4 cites both 0 and 3
+        let links = vec![vec![], vec![], vec![], vec![2], vec![0, 3, 3]];
+        let order = vec!["a", "b", "c", "d", "e"];
+
+        let related = related_posts(&tags, &links, &order, 2);
+        assert_eq!(related[0], vec![4, 1]);
+        assert_eq!(related[1], vec![0, 2]);
+        assert_eq!(related[2], vec![3, 0]);
+        assert_eq!(related[3], vec![2, 4]);
+        assert_eq!(related[4], vec![0, 3]);
+    }
+
+    #[test]
+    fn ignore_common_tags() {
+        let mut tags = vec![vec!["blog".to_string()]; 200];
+        tags[0].push("rare".to_string());
+        tags[1].push("rare".to_string());
+        let order: Vec<usize> = (0..tags.len()).collect();
+
+        let related = related_posts(&tags, &vec![vec![]; tags.len()], &order, 5);
+        assert_eq!(related[0], vec![1]);
+        assert!(related[2].is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::context::GeneratorContext;
+use crate::markdown::PostType;
+use crate::text::plain_text;
+use regex::Regex;
+use serde::Serialize;
+use std::fs;
+
+lazy_static! {
+    static ref HEADING: Regex = Regex::new(r#"(?s)<h[2-6] id="([^"]*)">(.*?)</h[2-6]>"#).unwrap();
+}
+
+/// A post within the search index, in a shape that can be loaded into e.g. Fuse.js or
+/// elasticlunr as it is.
+#[derive(Serialize)]
+struct SearchDocument {
+    title: String,
+    url: String,
+    tags: Vec<String>,
+    headings: Vec<SearchHeading>,
+    body: String,
+}
+
+#[derive(Serialize)]
+struct SearchHeading {
+    title: String,
+    url: String,
+}
+
+/// Writes a JSON search index of all posts except those with `search: false`, and of pages with
+/// `search: true`.
+pub fn generate_search_index(ctx: &GeneratorContext) {
+    let documents: Vec<SearchDocument> = ctx
+        .posts
+        .iter()
+        .filter(|(file, post)| {
+            file.frontmatter
+                .search
+                .unwrap_or(post.post_type == PostType::Post)
+        })
+        .map(|(_, post)| {
+            let url = format!("{}/{}", ctx.config.base_url, post.relative_url);
+            SearchDocument {
+                title: post.title.clone(),
+                tags: post.tags.clone(),
+                headings: HEADING
+                    .captures_iter(&post.markdown)
+                    .map(|cap| SearchHeading {
+                        title: plain_text(&cap[2]),
+                        url: format!("{}#{}", url, &cap[1]),
+                    })
+                    .collect(),
+                body: plain_text(&post.markdown),
+                url,
+            }
+        })
+        .collect();
+
+    let output_file = ctx.output_dir.join(&ctx.config.search_path);
+    fs::create_dir_all(output_file.parent().unwrap()).expect("Failed to create directory");
+    fs::write(output_file, serde_json::to_string(&documents).unwrap())
+        .expect("Failed to write search index");
+}
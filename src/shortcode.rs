@@ -0,0 +1,319 @@
+use crate::footnote::{fence_marker, indented_code_blocks};
+use log::warn;
+use regex::{Captures, Regex};
+use std::path::Path;
+use tera::{Context, Tera, Value};
+
+lazy_static! {
+    static ref INLINE_SHORTCODE: Regex =
+        Regex::new(r"\{\{\s*([A-Za-z_][\w-]*)\((.*?)\)\s*\}\}").unwrap();
+    static ref BLOCK_SHORTCODE: Regex =
+        Regex::new(r"(?s)\{%\s*([A-Za-z_][\w-]*)\((.*?)\)\s*%\}\r?\n?(.*?)\r?\n?\{%\s*end\s*%\}")
+            .unwrap();
+    static ref ARGUMENT: Regex = Regex::new(
+        r#"([A-Za-z_]\w*)\s*=\s*("(?:[^"\\]|\\.)*"|'[^']*'|true|false|-?\d+(?:\.\d+)?)"#
+    )
+    .unwrap();
+}
+
+/// Renders Zola-style shortcodes through the templates in `shortcodes/`, e.g.
+/// `{{ youtube(id="...") }}` using `shortcodes/youtube.html`, or `{% note() %}body{% end %}`,
+/// which passes the body to `shortcodes/note.html` as `body`. Since `.html` templates are
+/// autoescaped, templates should use `{{ body | safe }}` to keep HTML within the body.
+///
+/// Shortcodes within code blocks and code spans are left untouched.
+pub fn render_shortcodes(markdown: &str, path: &Path, tera: &Tera, base_url: &str) -> String {
+    let shortcodes = Shortcodes {
+        path,
+        tera,
+        base_url,
+    };
+    let mut result = String::with_capacity(markdown.len());
+    let mut text = String::new();
+    let mut fence: Option<String> = None;
+    for line in markdown.split_inclusive('\n') {
+        if let Some(marker) = &fence {
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+            }
+            result.push_str(line);
+        } else if let Some(marker) = fence_marker(line) {
+            result.push_str(&shortcodes.render_text(&text));
+            text.clear();
+            result.push_str(line);
+            fence = Some(marker);
+        } else {
+            text.push_str(line);
+        }
+    }
+    result.push_str(&shortcodes.render_text(&text));
+    result
+}
+
+/// What's needed to render the shortcodes of a single note.
+struct Shortcodes<'a> {
+    path: &'a Path,
+    tera: &'a Tera,
+    base_url: &'a str,
+}
+
+impl Shortcodes<'_> {
+    /// Renders the shortcodes in text outside of fenced code blocks, skipping indented ones.
+    fn render_text(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut offset = 0;
+        for code_block in indented_code_blocks(text) {
+            result.push_str(&self.render_outside_code_blocks(&text[offset..code_block.start]));
+            result.push_str(&text[code_block.clone()]);
+            offset = code_block.end;
+        }
+        result.push_str(&self.render_outside_code_blocks(&text[offset..]));
+        result
+    }
+
+    fn render_outside_code_blocks(&self, text: &str) -> String {
+        let (text, code_spans) = mask_code_spans(text);
+        let text = BLOCK_SHORTCODE.replace_all(&text, |cap: &Captures| {
+            self.render(&cap[0], &cap[1], &cap[2], Some(&cap[3]))
+        });
+        let text = INLINE_SHORTCODE.replace_all(&text, |cap: &Captures| {
+            self.render(&cap[0], &cap[1], &cap[2], None)
+        });
+        unmask_code_spans(&text, &code_spans)
+    }
+
+    fn render(&self, original: &str, name: &str, arguments: &str, body: Option<&str>) -> String {
+        let path = self.path.to_str().unwrap();
+        let template = format!("shortcodes/{}.html", name);
+        if !self.tera.get_template_names().any(|t| t == template) {
+            warn!(
+                "Unknown shortcode '{}' in {}, no template {}",
+                name, path, template
+            );
+            return original.into();
+        }
+
+        let arguments = match parse_arguments(arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => {
+                warn!(
+                    "Could not parse arguments of shortcode '{}' in {}: {}",
+                    name, path, e
+                );
+                return original.into();
+            }
+        };
+        let mut context = Context::new();
+        context.insert("base_url", self.base_url);
+        for (key, value) in arguments {
+            context.insert(key, &value);
+        }
+        if let Some(body) = body {
+            context.insert("body", body);
+        }
+
+        match self.tera.render(&template, &context) {
+            Ok(rendered) => rendered.trim_end().into(),
+            Err(e) => {
+                warn!("Could not render shortcode '{}' in {}: {}", name, path, e);
+                original.into()
+            }
+        }
+    }
+}
+
+/// Replaces code spans, e.g. `` `{{ a() }}` ``, with placeholders so that shortcodes within them
+/// aren't rendered.
+pub fn mask_code_spans(text: &str) -> (String, Vec<String>) {
+    let mut masked = String::with_capacity(text.len());
+    let mut code_spans = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let after_opening = start + ticks;
+        let closing = find_backtick_run(&rest[after_opening..], ticks);
+        masked.push_str(&rest[..start]);
+        match closing {
+            Some(end) => {
+                let end = after_opening + end + ticks;
+                masked.push_str(&format!("\u{0}{}\u{0}", code_spans.len()));
+                code_spans.push(rest[start..end].to_string());
+                rest = &rest[end..];
+            }
+            None => {
+                masked.push_str(&rest[start..after_opening]);
+                rest = &rest[after_opening..];
+            }
+        }
+    }
+    masked.push_str(rest);
+    (masked, code_spans)
+}
+
+/// Finds a run of exactly `ticks` backticks, which closes a code span opened by as many.
+fn find_backtick_run(text: &str, ticks: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('`') {
+        let start = offset + start;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == ticks {
+            return Some(start);
+        }
+        offset = start + run;
+    }
+    None
+}
+
+pub fn unmask_code_spans(text: &str, code_spans: &[String]) -> String {
+    let mut text = text.to_string();
+    for (i, code_span) in code_spans.iter().enumerate() {
+        text = text.replace(&format!("\u{0}{}\u{0}", i), code_span);
+    }
+    text
+}
+
+/// Parses the `key="value", other=1` argument list of a shortcode.
+fn parse_arguments(arguments: &str) -> Result<Vec<(String, Value)>, String> {
+    // anything between the arguments other than a comma is an argument that couldn't be parsed,
+    // e.g. `id=foo` with a missing quote
+    let mut offset = 0;
+    for (i, argument) in ARGUMENT.find_iter(arguments).enumerate() {
+        check_separator(
+            &arguments[offset..argument.start()],
+            i > 0,
+            argument.as_str(),
+        )?;
+        offset = argument.end();
+    }
+    let rest = arguments[offset..].trim();
+    let trailing_comma = offset > 0 && rest == ",";
+    if !rest.is_empty() && !trailing_comma {
+        return Err(format!(
+            "invalid argument `{}`",
+            rest.trim_start_matches(',').trim()
+        ));
+    }
+
+    ARGUMENT
+        .captures_iter(arguments)
+        .map(|cap| {
+            let raw = &cap[2];
+            let value = if raw.starts_with('"') {
+                serde_json::from_str(raw).unwrap_or_else(|_| Value::from(&raw[1..raw.len() - 1]))
+            } else if raw.starts_with('\'') {
+                Value::from(&raw[1..raw.len() - 1])
+            } else if raw == "true" || raw == "false" {
+                Value::from(raw == "true")
+            } else {
+                // unlike JSON, numbers may have leading zeros, e.g. `007`
+                raw.parse::<i64>()
+                    .map(Value::from)
+                    .or_else(|_| raw.parse::<f64>().map(Value::from))
+                    .map_err(|_| format!("invalid number {}", raw))?
+            };
+            Ok((cap[1].to_string(), value))
+        })
+        .collect()
+}
+
+/// Checks the text before an argument, which must be a comma unless it's the first argument.
+fn check_separator(separator: &str, comma: bool, argument: &str) -> Result<(), String> {
+    let trimmed = separator.trim();
+    if (comma && trimmed == ",") || (!comma && trimmed.is_empty()) {
+        Ok(())
+    } else if trimmed.is_empty() {
+        Err(format!("missing `,` before `{}`", argument))
+    } else {
+        let trimmed = trimmed.trim_start_matches(',').trim();
+        Err(format!("invalid argument `{}`", trimmed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_arguments, render_shortcodes};
+    use std::path::Path;
+    use tera::Tera;
+
+    fn tera() -> Tera {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (
+                "shortcodes/youtube.html",
+                "<iframe src=\"https://www.youtube.com/embed/{{ id }}\" width=\"{{ width }}\"></iframe>",
+            ),
+            (
+                "shortcodes/note.html",
+                "<aside class=\"{{ kind | default(value='note') }}\">\n\n{{ body | safe }}\n\n</aside>",
+            ),
+        ])
+        .unwrap();
+        tera
+    }
+
+    #[test]
+    fn renders_inline_shortcodes() {
+        let markdown = "Watch this:\n\n{{ youtube(id=\"dQw4w9WgXcQ\", width=640) }}\n";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            "Watch this:\n\n<iframe src=\"https://www.youtube.com/embed/dQw4w9WgXcQ\" width=\"640\"></iframe>\n"
+        );
+    }
+
+    #[test]
+    fn renders_block_shortcodes() {
+        let markdown = "{% note(kind='warning') %}\nSome *text* and <b>`code`</b>\n{% end %}\n";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            "<aside class=\"warning\">\n\nSome *text* and <b>`code`</b>\n\n</aside>\n"
+        );
+    }
+
+    #[test]
+    fn ignores_code_and_unknown_shortcodes() {
+        let markdown = "```\n{{ youtube(id=\"x\") }}\n```\n{{ unknown() }}\n\
+                        Use ``{{ youtube(id=\"x\") }}`` or `{{ youtube() }}`\n";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            markdown
+        );
+    }
+
+    #[test]
+    fn parses_numbers_with_leading_zeros() {
+        let markdown = "{{ youtube(id=\"x\", width=007) }}";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            "<iframe src=\"https://www.youtube.com/embed/x\" width=\"7\"></iframe>"
+        );
+    }
+
+    #[test]
+    fn ignores_code_within_longer_fences_and_indented_code() {
+        let markdown =
+            "````\n```\n{{ youtube(id=\"x\") }}\n```\n````\n\n    {{ youtube(id=\"x\") }}\n";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            markdown
+        );
+    }
+
+    #[test]
+    fn reports_malformed_arguments() {
+        assert_eq!(
+            parse_arguments("id=foo").unwrap_err(),
+            "invalid argument `id=foo`"
+        );
+        assert_eq!(
+            parse_arguments("id=\"x\" width=640").unwrap_err(),
+            "missing `,` before `width=640`"
+        );
+        assert_eq!(parse_arguments(" id=\"x\", width=640, ").unwrap().len(), 2);
+
+        let markdown = "{{ youtube(id=foo) }}";
+        assert_eq!(
+            render_shortcodes(markdown, Path::new("Note.md"), &tera(), ""),
+            markdown
+        );
+    }
+}
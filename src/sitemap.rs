@@ -0,0 +1,122 @@
+use crate::build::render_template;
+use crate::context::{GeneratorContext, PostLink, SeriesContext};
+use crate::navigation;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+/// The maximum number of URLs within a single sitemap, as per sitemaps.org.
+const MAX_URLS_PER_SITEMAP: usize = 50_000;
+
+#[derive(Serialize)]
+struct SitemapEntry {
+    loc: String,
+    lastmod: Option<String>,
+}
+
+/// Writes `sitemap.xml` listing all generated pages, which is split into several sitemaps and a
+/// sitemap index for large sites.
+pub fn generate_sitemap(ctx: &GeneratorContext) {
+    let entries = sitemap_entries(ctx);
+    if entries.len() <= MAX_URLS_PER_SITEMAP {
+        write_sitemap(ctx, "sitemap.xml", &entries);
+        return;
+    }
+
+    let mut sitemaps = Vec::new();
+    for (i, chunk) in entries.chunks(MAX_URLS_PER_SITEMAP).enumerate() {
+        let file_name = format!("sitemap{}.xml", i + 1);
+        write_sitemap(ctx, &file_name, chunk);
+        sitemaps.push(format!("{}/{}", ctx.config.base_url, file_name));
+    }
+
+    let mut context = ctx.global_context.clone();
+    context.insert("sitemaps", &sitemaps);
+    let index = render_template(
+        ctx,
+        "sitemap_index.xml",
+        include_str!("templates/sitemap_index.xml"),
+        &context,
+    );
+    fs::write(ctx.output_dir.join("sitemap.xml"), index).expect("Failed to write sitemap");
+}
+
+/// Writes `robots.txt`, pointing crawlers to the sitemap.
+pub fn generate_robots(ctx: &GeneratorContext) {
+    let robots = render_template(
+        ctx,
+        "robots.txt",
+        include_str!("templates/robots.txt"),
+        &ctx.global_context,
+    );
+    fs::write(ctx.output_dir.join("robots.txt"), robots).expect("Failed to write robots.txt");
+}
+
+fn write_sitemap(ctx: &GeneratorContext, file_name: &str, entries: &[SitemapEntry]) {
+    let mut context = ctx.global_context.clone();
+    context.insert("entries", entries);
+    let sitemap = render_template(
+        ctx,
+        "sitemap.xml",
+        include_str!("templates/sitemap.xml"),
+        &context,
+    );
+    fs::write(ctx.output_dir.join(file_name), sitemap).expect("Failed to write sitemap");
+}
+
+/// Lists every generated page, last modified when any post it shows was, preferring a post's
+/// `updated` date over its `date`.
+fn sitemap_entries(ctx: &GeneratorContext) -> Vec<SitemapEntry> {
+    let updated: HashMap<&str, &String> = ctx
+        .posts
+        .iter()
+        .filter_map(|(_, post)| Some((post.relative_url.as_str(), post.updated.as_ref()?)))
+        .collect();
+    let last_modified = |posts: &[PostLink]| {
+        posts
+            .iter()
+            .filter_map(|post| updated.get(post.relative_url.as_str()))
+            .max()
+            .map(|date| date.to_string())
+    };
+
+    let mut entries = vec![SitemapEntry {
+        loc: format!("{}/", ctx.config.base_url),
+        lastmod: updated.values().max().map(|date| date.to_string()),
+    }];
+    for (_, post) in &ctx.posts {
+        entries.push(SitemapEntry {
+            loc: format!("{}/{}", ctx.config.base_url, post.relative_url),
+            lastmod: post.updated.clone(),
+        });
+    }
+
+    let tags = navigation::tags(ctx);
+    entries.push(SitemapEntry {
+        loc: format!("{}/tags/", ctx.config.base_url),
+        lastmod: tags
+            .iter()
+            .filter_map(|tag| last_modified(&tag.posts))
+            .max(),
+    });
+    for tag in &tags {
+        entries.push(SitemapEntry {
+            loc: format!("{}/{}", ctx.config.base_url, tag.relative_url),
+            lastmod: last_modified(&tag.posts),
+        });
+    }
+
+    let mut series: BTreeMap<&str, &SeriesContext> = BTreeMap::new();
+    for (_, post) in &ctx.posts {
+        if let Some(s) = &post.series {
+            series.insert(&s.relative_url, s);
+        }
+    }
+    for (relative_url, series) in series {
+        entries.push(SitemapEntry {
+            loc: format!("{}/{}", ctx.config.base_url, relative_url),
+            lastmod: last_modified(&series.parts),
+        });
+    }
+    entries
+}
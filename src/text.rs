@@ -0,0 +1,147 @@
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+
+lazy_static! {
+    static ref FOOTNOTE_LINK: Regex =
+        Regex::new(r#"(?s)<sup class="fn">.*?</sup>|<a class="fn-back"[^>]*>.*?</a>"#).unwrap();
+    static ref BLOCK_TAG: Regex =
+        Regex::new(r"(?s)</?(p|h[1-6]|li|t[dh]|div|blockquote|pre|figure|br|hr)\b[^>]*>").unwrap();
+    static ref TAG: Regex = Regex::new(r"(?s)<[^>]*>").unwrap();
+    static ref WHITESPACE: Regex = Regex::new(r"\s+").unwrap();
+}
+
+/// The text within a post's HTML, without any tags, footnotes or sidenotes and on a single line.
+pub fn plain_text(html: &str) -> String {
+    let text = strip_sidenotes(html);
+    let text = FOOTNOTE_LINK.replace_all(&text, "");
+    let text = BLOCK_TAG.replace_all(&text, " ");
+    let text = TAG.replace_all(&text, "");
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    WHITESPACE.replace_all(text.trim(), " ").into_owned()
+}
+
+/// Removes sidenotes, i.e. `<span class="sidenote">` and any spans within it.
+fn strip_sidenotes(html: &str) -> String {
+    const SIDENOTE: &str = "<span class=\"sidenote\">";
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(SIDENOTE) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + SIDENOTE.len()..];
+        let mut depth = 1;
+        while depth > 0 {
+            let opening = rest.find("<span");
+            let closing = match rest.find("</span>") {
+                Some(closing) => closing,
+                None => return result,
+            };
+            match opening {
+                Some(opening) if opening < closing => {
+                    depth += 1;
+                    rest = &rest[opening + "<span".len()..];
+                }
+                _ => {
+                    depth -= 1;
+                    rest = &rest[closing + "</span>".len()..];
+                }
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// A post's summary for posts without `<!-- more -->`: its `description` or first words.
+pub fn summarize(html: &str, description: Option<&str>, words: usize) -> String {
+    let text = match description {
+        Some(description) => description.to_string(),
+        None => {
+            let text = plain_text(html);
+            let mut split = text.split(' ');
+            let summary = split.by_ref().take(words).collect::<Vec<&str>>().join(" ");
+            if split.next().is_some() {
+                format!("{}…", summary)
+            } else {
+                summary
+            }
+        }
+    };
+    let mut escaped = String::new();
+    escape_html(&mut escaped, &text).unwrap();
+    format!("<p>{}</p>", escaped)
+}
+
+/// Counts the words in a text. Since Chinese and Japanese aren't written with spaces between words,
+/// each of their characters is counted as a word on its own.
+pub fn word_count(text: &str) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            count += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                count += 1;
+            }
+            in_word = true;
+        } else if c.is_whitespace() || !matches!(c, '\'' | '’' | '-' | '_' | '.' | ',') {
+            in_word = false;
+        }
+    }
+    count
+}
+
+/// Whether a character is a Chinese character or Japanese kana.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Unified Ideographs Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2ffff}' // CJK Unified Ideographs Extension B and later
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plain_text, summarize, word_count};
+
+    #[test]
+    fn strip_html() {
+        assert_eq!(
+            plain_text("<h2 id=\"a\">A &amp; B</h2>\n<p>Some <em>text</em>.</p><p>More</p>"),
+            "A & B Some text. More"
+        );
+        assert_eq!(
+            plain_text(
+                "<p>Text<label for=\"sn-a-1\" class=\"margin-toggle sidenote-number\"></label>\
+                 <input type=\"checkbox\" id=\"sn-a-1\" class=\"margin-toggle\"/>\
+                 <span class=\"sidenote\">A <span>nested</span> note</span> and\
+                 <sup class=\"fn\"><a id=\"b-back\" href=\"#b\">[1]</a></sup> more.</p>"
+            ),
+            "Text and more."
+        );
+    }
+
+    #[test]
+    fn summary_from_description_or_words() {
+        let html = "<p>One <em>two</em> three</p>\n";
+        assert_eq!(summarize(html, Some("A & B"), 2), "<p>A &amp; B</p>");
+        assert_eq!(summarize(html, None, 2), "<p>One two…</p>");
+        assert_eq!(summarize(html, None, 3), "<p>One two three</p>");
+    }
+
+    #[test]
+    fn count_words() {
+        assert_eq!(word_count("It's a well-known fact, 3.5 times."), 6);
+        assert_eq!(word_count("日本語の文章です"), 8);
+        assert_eq!(word_count("Campfire は 静的サイト"), 7);
+        assert_eq!(word_count("한국어 문장"), 2);
+    }
+}
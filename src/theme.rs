@@ -0,0 +1,109 @@
+use crate::config::Config;
+use log::debug;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tera::Tera;
+
+/// The built-in theme, used for any template the vault doesn't have.
+const DEFAULT_TEMPLATES: [(&str, &str); 9] = [
+    ("404.html", include_str!("templates/theme/404.html")),
+    ("base.html", include_str!("templates/theme/base.html")),
+    ("index.html", include_str!("templates/theme/index.html")),
+    ("macros.html", include_str!("templates/theme/macros.html")),
+    ("page.html", include_str!("templates/theme/page.html")),
+    ("post.html", include_str!("templates/theme/post.html")),
+    ("series.html", include_str!("templates/theme/series.html")),
+    ("tag.html", include_str!("templates/theme/tag.html")),
+    ("tags.html", include_str!("templates/theme/tags.html")),
+];
+
+/// A theme's `theme.yaml`.
+#[derive(Debug, Deserialize, Default)]
+struct ThemeConfig {
+    /// Defaults for the site's `extra` values.
+    #[serde(default)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+/// The directory of the configured theme, if any.
+pub fn theme_dir(base_dir: &Path, config: &Config) -> Option<PathBuf> {
+    config
+        .theme
+        .as_ref()
+        .map(|name| base_dir.join(".campfire").join("themes").join(name))
+}
+
+/// Checks that the configured theme exists, and adds the `extra` values from its `theme.yaml`
+/// unless the site sets them itself.
+pub fn apply_theme_config(base_dir: &Path, config: &mut Config) -> Result<(), Box<dyn Error>> {
+    let theme_dir = match theme_dir(base_dir, config) {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    if !theme_dir.is_dir() {
+        return Err(format!("Theme not found: {}", theme_dir.to_str().unwrap()).into());
+    }
+
+    let theme_config = theme_dir.join("theme.yaml");
+    let theme_config: ThemeConfig = if theme_config.exists() {
+        serde_yaml::from_reader(BufReader::new(File::open(theme_config)?))?
+    } else {
+        ThemeConfig::default()
+    };
+    for (key, value) in theme_config.extra {
+        config.extra.entry(key).or_insert(value);
+    }
+    Ok(())
+}
+
+/// Loads the vault's templates, falling back to the configured theme's templates and then to the
+/// built-in theme for all others. A template may extend one from a later layer, e.g. a custom
+/// `post.html` can use the theme's or built-in `base.html`.
+pub fn load_templates(base_dir: &Path, config: &Config) -> tera::Result<Tera> {
+    let template_dir = base_dir.join(".campfire").join(&config.paths.templates);
+    let mut tera = parse_templates(&template_dir)?.unwrap_or_else(|| {
+        debug!("No templates found in the vault");
+        Tera::default()
+    });
+
+    if let Some(theme_dir) = theme_dir(base_dir, config) {
+        if let Some(theme) = parse_templates(&theme_dir.join("templates"))? {
+            tera.extend(&theme)?;
+        }
+    }
+
+    let mut defaults = Tera::default();
+    defaults.add_raw_templates(DEFAULT_TEMPLATES.to_vec())?;
+    tera.extend(&defaults)?;
+    Ok(tera)
+}
+
+/// Parses all templates within a directory, if it exists.
+fn parse_templates(template_dir: &Path) -> tera::Result<Option<Tera>> {
+    if !template_dir.is_dir() {
+        return Ok(None);
+    }
+    let template_path = template_dir
+        .canonicalize()?
+        .join("**")
+        .join("*.{html,xml,txt,json}");
+    let template_path = template_path.to_str().unwrap();
+    debug!("Using templates from {}", template_path);
+    Ok(Some(Tera::parse(template_path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DEFAULT_TEMPLATES;
+    use tera::Tera;
+
+    #[test]
+    fn parse_default_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(DEFAULT_TEMPLATES.to_vec()).unwrap();
+    }
+}
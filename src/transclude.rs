@@ -0,0 +1,297 @@
+use crate::context::GeneratorContext;
+use crate::footnote::fence_marker;
+use crate::links::{is_relative_url, resolve_link};
+use crate::markdown::strip_frontmatter;
+use crate::shortcode::{mask_code_spans, unmask_code_spans};
+use log::warn;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag};
+use regex::{Captures, Regex};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Characters escaped in rewritten link destinations, so that they stay a single destination and
+/// are decoded to the same path again.
+const LINK_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'%')
+    .add(b'(')
+    .add(b')')
+    .add(b'<')
+    .add(b'>');
+
+/// How many notes deep transclusions may be nested.
+const MAX_DEPTH: usize = 8;
+
+lazy_static! {
+    static ref EMBED: Regex =
+        Regex::new(r"!\[\[([^\]|#]+)(?:#([^\]|]+))?(?:\|[^\]]*)?\]\]").unwrap();
+    static ref HEADING: Regex = Regex::new(r"^(#{1,6})\s+(.*?)\s*#*\s*$").unwrap();
+    static ref FOOTNOTE_LABEL: Regex = Regex::new(r"\[\^([^\]\s]+)\]").unwrap();
+}
+
+/// The notes being transcluded into each other, and how many embeds there were so far.
+struct Transclusion {
+    stack: Vec<PathBuf>,
+    embeds: usize,
+}
+
+/// Replaces Obsidian's `![[Other Note]]` and `![[Other Note#Heading]]` embeds with the content of
+/// the referenced note, or the section below the heading. Embeds of anything but notes, e.g.
+/// images, are kept as they are.
+pub fn transclude(markdown: &str, ctx: &GeneratorContext, path: &Path) -> String {
+    let mut transclusion = Transclusion {
+        stack: vec![path.to_path_buf()],
+        embeds: 0,
+    };
+    transclude_recursively(markdown, ctx, &mut transclusion)
+}
+
+fn transclude_recursively(
+    markdown: &str,
+    ctx: &GeneratorContext,
+    transclusion: &mut Transclusion,
+) -> String {
+    let mut result = String::with_capacity(markdown.len());
+    let mut text = String::new();
+    let mut fence: Option<String> = None;
+    for line in markdown.split_inclusive('\n') {
+        if let Some(marker) = &fence {
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+            }
+            result.push_str(line);
+        } else if let Some(marker) = fence_marker(line) {
+            result.push_str(&replace_embeds(&text, ctx, transclusion));
+            text.clear();
+            fence = Some(marker);
+            result.push_str(line);
+        } else {
+            text.push_str(line);
+        }
+    }
+    result.push_str(&replace_embeds(&text, ctx, transclusion));
+    result
+}
+
+/// Replaces the embeds within text outside of fenced code blocks, keeping code spans as they are.
+fn replace_embeds(text: &str, ctx: &GeneratorContext, transclusion: &mut Transclusion) -> String {
+    let (text, code_spans) = mask_code_spans(text);
+    let replaced = EMBED.replace_all(&text, |cap: &Captures| {
+        embed(ctx, transclusion, &cap[1], cap.get(2).map(|m| m.as_str()))
+            .unwrap_or_else(|| cap[0].to_string())
+    });
+    unmask_code_spans(&replaced, &code_spans)
+}
+
+/// Returns the markdown replacing an embed, or `None` if it doesn't refer to a note.
+fn embed(
+    ctx: &GeneratorContext,
+    transclusion: &mut Transclusion,
+    target: &str,
+    heading: Option<&str>,
+) -> Option<String> {
+    let target = target.trim();
+    let path = match find_note(ctx, target.strip_suffix(".md").unwrap_or(target)) {
+        Some(path) => path,
+        // e.g. `![[image.png]]`, which isn't a note
+        None if !target.ends_with(".md") && Path::new(target).extension().is_some() => return None,
+        None => {
+            warn!("Unable to resolve transcluded note: {}", target);
+            return Some(String::new());
+        }
+    };
+    let published = ctx.posts.iter().any(|(file, _)| file.path == path);
+    if !published && !ctx.config.transclude_unpublished {
+        warn!(
+            "Not transcluding unpublished note {}, enable transclude-unpublished to allow this",
+            target
+        );
+        return Some(String::new());
+    }
+    if transclusion.stack.contains(&path) {
+        warn!("Not transcluding {}, it would include itself", target);
+        return Some(String::new());
+    }
+    if transclusion.stack.len() > MAX_DEPTH {
+        warn!("Not transcluding {}, notes are nested too deeply", target);
+        return Some(String::new());
+    }
+
+    let content = fs::read_to_string(&path).ok()?;
+    let mut markdown = strip_frontmatter(&content).to_string();
+    if let Some(heading) = heading {
+        markdown = match extract_section(&markdown, heading) {
+            Some(section) => section,
+            None => {
+                warn!("Unable to find heading {} in {}", heading, target);
+                return Some(String::new());
+            }
+        };
+    }
+
+    transclusion.embeds += 1;
+    let markdown = rewrite_links(ctx, &path, &markdown);
+    let markdown = namespace_footnotes(&markdown, transclusion.embeds);
+
+    transclusion.stack.push(path);
+    let markdown = transclude_recursively(&markdown, ctx, transclusion);
+    transclusion.stack.pop();
+    Some(format!(
+        "\n<div class=\"transclusion\">\n\n{}\n\n</div>\n",
+        markdown.trim()
+    ))
+}
+
+/// Makes relative links and images within a transcluded note relative to the vault, since they'd
+/// otherwise be resolved relative to the note it's embedded into.
+fn rewrite_links(ctx: &GeneratorContext, source: &Path, markdown: &str) -> String {
+    let mut replacements = Vec::new();
+    let parser = Parser::new_ext(markdown, Options::ENABLE_FOOTNOTES).into_offset_iter();
+    for (event, range) in parser {
+        let dest = match event {
+            Event::Start(Tag::Link(LinkType::Inline, dest, _))
+            | Event::Start(Tag::Image(LinkType::Inline, dest, _)) => dest,
+            _ => continue,
+        };
+        if !is_relative_url(&dest) || dest.starts_with('/') {
+            continue;
+        }
+        let link = match resolve_link(ctx, source, &dest) {
+            Some(link) => link,
+            None => continue,
+        };
+        let raw = &markdown[range.clone()];
+        let start = raw.match_indices("](").find_map(|(i, _)| {
+            let after = &raw[i + 2..];
+            let after = after.trim_start().trim_start_matches('<');
+            after
+                .starts_with(dest.as_ref())
+                .then(|| range.start + raw.len() - after.len())
+        });
+        if let Some(start) = start {
+            let path = link.path.to_str().unwrap().replace('\\', "/");
+            let new_dest = format!("/{}{}", utf8_percent_encode(&path, LINK_PATH), link.suffix);
+            replacements.push((start, start + dest.len(), new_dest));
+        }
+    }
+
+    let mut markdown = markdown.to_string();
+    replacements.sort_by_key(|(start, _, _)| *start);
+    for (start, end, new_dest) in replacements.into_iter().rev() {
+        markdown.replace_range(start..end, &new_dest);
+    }
+    markdown
+}
+
+/// Prefixes the footnote labels of a transcluded note, so that they don't collide with the ones
+/// of the note it's embedded into.
+fn namespace_footnotes(markdown: &str, embed: usize) -> String {
+    let mut result = String::with_capacity(markdown.len());
+    let mut fence: Option<String> = None;
+    for line in markdown.split_inclusive('\n') {
+        if let Some(marker) = &fence {
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+            }
+            result.push_str(line);
+        } else if let Some(marker) = fence_marker(line) {
+            fence = Some(marker);
+            result.push_str(line);
+        } else {
+            let replaced = FOOTNOTE_LABEL.replace_all(line, |cap: &Captures| {
+                format!("[^embed{}-{}]", embed, &cap[1])
+            });
+            result.push_str(&replaced);
+        }
+    }
+    result
+}
+
+/// Finds a note the way Obsidian does, by its path within the vault or just by its name.
+fn find_note(ctx: &GeneratorContext, target: &str) -> Option<PathBuf> {
+    let target = target.trim_start_matches('/');
+    let relative_name = |path: &PathBuf| {
+        path.strip_prefix(&ctx.base_dir)
+            .unwrap()
+            .with_extension("")
+            .to_str()
+            .unwrap()
+            .replace('\\', "/")
+    };
+    ctx.notes
+        .iter()
+        .find(|path| relative_name(path) == target)
+        .or_else(|| {
+            ctx.notes
+                .iter()
+                .find(|path| relative_name(path).ends_with(&format!("/{}", target)))
+        })
+        .cloned()
+}
+
+/// Returns the content below the given heading, up to the next heading of the same or a higher
+/// level.
+fn extract_section(markdown: &str, heading: &str) -> Option<String> {
+    let mut section: Option<(usize, String)> = None;
+    let mut fence: Option<String> = None;
+    for line in markdown.lines() {
+        if let Some(marker) = &fence {
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+            }
+        } else if let Some(marker) = fence_marker(line) {
+            fence = Some(marker);
+        } else if let Some(cap) = HEADING.captures(line) {
+            let level = cap[1].len();
+            match &section {
+                Some((section_level, _)) if level <= *section_level => break,
+                None if cap[2].eq_ignore_ascii_case(heading.trim()) => {
+                    section = Some((level, String::new()));
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((_, content)) = &mut section {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    section.map(|(_, content)| content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_section, namespace_footnotes, transclude};
+    use crate::context::test_context;
+
+    #[test]
+    fn ignore_embeds_in_code_spans() {
+        let ctx = test_context("name: blog\nrequire-tag: published\n");
+        let note = ctx.base_dir.join("A.md");
+        assert_eq!(
+            transclude("Use `![[B]]` to embed ![[Missing]]\n", &ctx, &note),
+            "Use `![[B]]` to embed \n"
+        );
+    }
+
+    #[test]
+    fn namespace_footnote_labels() {
+        assert_eq!(
+            namespace_footnotes("A[^1].\n```\n[^2]\n```\n[^1]: Note\n", 3),
+            "A[^embed3-1].\n```\n[^2]\n```\n[^embed3-1]: Note\n"
+        );
+    }
+
+    #[test]
+    fn extract_section_until_next_heading_of_same_level() {
+        let markdown = "# Title\nIntro\n## Usage\nSome text\n```\n# not a heading\n```\n### Details\nMore\n## Other\nIgnored\n";
+        assert_eq!(
+            extract_section(markdown, "usage").unwrap(),
+            "Some text\n```\n# not a heading\n```\n### Details\nMore\n"
+        );
+        assert_eq!(extract_section(markdown, "Missing"), None);
+    }
+}